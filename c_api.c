@@ -6,20 +6,21 @@ jit_label_t emptylbl() {
     return ((jit_label_t)~((jit_uint)0));
 };
 
+// debug dumps go to stderr, stdout belongs to the script
 void printfunc(jit_function_t func) {
-    jit_dump_function(stdout, func, "dumpfunc");
-    putchar('\n');
-    fflush(stdout);
+    jit_dump_function(stderr, func, "dumpfunc");
+    fputc('\n', stderr);
+    fflush(stderr);
 };
 
 void printtype(jit_type_t tp) {
-    jit_dump_type(stdout, tp);
-    putchar('\n');
-    fflush(stdout);
+    jit_dump_type(stderr, tp);
+    fputc('\n', stderr);
+    fflush(stderr);
 };
 
 void printval(jit_function_t func, jit_value_t val) {
-    jit_dump_value(stdout, func, val, NULL);
-    putchar('\n');
-    fflush(stdout);
+    jit_dump_value(stderr, func, val, NULL);
+    fputc('\n', stderr);
+    fflush(stderr);
 }
\ No newline at end of file
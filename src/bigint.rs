@@ -0,0 +1,324 @@
+use crate::runtime;
+use crate::wrapper::RESULT_DIVISION_BY_ZERO;
+use libc::c_void;
+use std::cmp::Ordering;
+use std::fmt;
+
+// arbitrary precision integer, the magnitude is in base 2^32 starting
+// from the least significant digit and has no leading zero digits
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BigInt {
+    neg: bool,
+    mag: Vec<u32>,
+}
+
+impl BigInt {
+    pub fn from_i64(n: i64) -> Self {
+        let abs = n.unsigned_abs();
+        BigInt {neg: n < 0, mag: vec![abs as u32, (abs >> 32) as u32]}.trim()
+    }
+
+    // decimal digits with an optional sign
+    pub fn parse(s: &str) -> Option<Self> {
+        let (neg, digits) = match s.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, s)
+        };
+        if digits.is_empty() {
+            return None;
+        }
+        let mut mag = Vec::new();
+        for c in digits.chars() {
+            mag = mul_small_add(&mag, 10, c.to_digit(10)?);
+        }
+        Some(BigInt {neg, mag}.trim())
+    }
+
+    fn trim(mut self) -> Self {
+        while self.mag.last() == Some(&0) {
+            self.mag.pop();
+        }
+        if self.mag.is_empty() {
+            self.neg = false;
+        }
+        self
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.mag.is_empty()
+    }
+
+    pub fn add(&self, other: &BigInt) -> BigInt {
+        if self.neg == other.neg {
+            return BigInt {neg: self.neg, mag: add_mag(&self.mag, &other.mag)}.trim();
+        }
+        // different signs, subtract the smaller magnitude from the bigger one
+        match cmp_mag(&self.mag, &other.mag) {
+            Ordering::Less => BigInt {neg: other.neg, mag: sub_mag(&other.mag, &self.mag)}.trim(),
+            _ => BigInt {neg: self.neg, mag: sub_mag(&self.mag, &other.mag)}.trim()
+        }
+    }
+
+    pub fn sub(&self, other: &BigInt) -> BigInt {
+        self.add(&BigInt {neg: !other.neg, mag: other.mag.clone()})
+    }
+
+    pub fn mul(&self, other: &BigInt) -> BigInt {
+        BigInt {neg: self.neg != other.neg, mag: mul_mag(&self.mag, &other.mag)}.trim()
+    }
+
+    // truncating division like for ints, the remainder has the sign of `self`
+    pub fn divmod(&self, other: &BigInt) -> Option<(BigInt, BigInt)> {
+        if other.is_zero() {
+            return None;
+        }
+        let (q, r) = divmod_mag(&self.mag, &other.mag);
+        Some((BigInt {neg: self.neg != other.neg, mag: q}.trim(), BigInt {neg: self.neg, mag: r}.trim()))
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &BigInt) -> Ordering {
+        match (self.neg, other.neg) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => cmp_mag(&self.mag, &other.mag),
+            (true, true) => cmp_mag(&other.mag, &self.mag)
+        }
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &BigInt) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_zero() {
+            return write!(f, "0");
+        }
+        // split into base 10^9 chunks, least significant first
+        let mut chunks = Vec::new();
+        let mut mag = self.mag.clone();
+        while !mag.is_empty() {
+            let (q, r) = div_small(&mag, 1_000_000_000);
+            chunks.push(r);
+            mag = q;
+        }
+        if self.neg {
+            write!(f, "-")?;
+        }
+        write!(f, "{}", chunks.pop().unwrap())?;
+        for chunk in chunks.iter().rev() {
+            write!(f, "{:09}", chunk)?;
+        }
+        Ok(())
+    }
+}
+
+fn cmp_mag(a: &[u32], b: &[u32]) -> Ordering {
+    a.len().cmp(&b.len()).then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add_mag(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut res = Vec::with_capacity(a.len().max(b.len()) + 1);
+    let mut carry = 0u64;
+    for i in 0..a.len().max(b.len()) {
+        let sum = *a.get(i).unwrap_or(&0) as u64 + *b.get(i).unwrap_or(&0) as u64 + carry;
+        res.push(sum as u32);
+        carry = sum >> 32;
+    }
+    res.push(carry as u32);
+    res
+}
+
+// requires a >= b
+fn sub_mag(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut res = Vec::with_capacity(a.len());
+    let mut borrow = 0i64;
+    for (i, &x) in a.iter().enumerate() {
+        let mut diff = x as i64 - *b.get(i).unwrap_or(&0) as i64 - borrow;
+        borrow = if diff < 0 { 1 } else { 0 };
+        if diff < 0 {
+            diff += 1 << 32;
+        }
+        res.push(diff as u32);
+    }
+    res
+}
+
+fn mul_mag(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut res = vec![0u32; a.len() + b.len()];
+    for (i, x) in a.iter().enumerate() {
+        let mut carry = 0u64;
+        for (j, y) in b.iter().enumerate() {
+            let cur = res[i + j] as u64 + (*x as u64) * (*y as u64) + carry;
+            res[i + j] = cur as u32;
+            carry = cur >> 32;
+        }
+        res[i + b.len()] = carry as u32;
+    }
+    res
+}
+
+fn mul_small_add(a: &[u32], m: u32, add: u32) -> Vec<u32> {
+    let mut res = Vec::with_capacity(a.len() + 1);
+    let mut carry = add as u64;
+    for x in a {
+        let cur = (*x as u64) * (m as u64) + carry;
+        res.push(cur as u32);
+        carry = cur >> 32;
+    }
+    res.push(carry as u32);
+    while res.last() == Some(&0) {
+        res.pop();
+    }
+    res
+}
+
+fn div_small(a: &[u32], d: u32) -> (Vec<u32>, u32) {
+    let mut q = vec![0u32; a.len()];
+    let mut rem = 0u64;
+    for i in (0..a.len()).rev() {
+        let cur = (rem << 32) | a[i] as u64;
+        q[i] = (cur / d as u64) as u32;
+        rem = cur % d as u64;
+    }
+    while q.last() == Some(&0) {
+        q.pop();
+    }
+    (q, rem as u32)
+}
+
+// schoolbook binary long division
+fn divmod_mag(a: &[u32], b: &[u32]) -> (Vec<u32>, Vec<u32>) {
+    if b.len() == 1 {
+        let (q, r) = div_small(a, b[0]);
+        return (q, vec![r]);
+    }
+    let mut q = vec![0u32; a.len()];
+    let mut r: Vec<u32> = Vec::new();
+    for i in (0..a.len() * 32).rev() {
+        r = mul_small_add(&r, 2, (a[i / 32] >> (i % 32)) & 1);
+        if cmp_mag(&r, b) != Ordering::Less {
+            r = sub_mag(&r, b);
+            while r.last() == Some(&0) {
+                r.pop();
+            }
+            q[i / 32] |= 1 << (i % 32);
+        }
+    }
+    (q, r)
+}
+
+fn boxed(n: BigInt) -> *mut c_void {
+    runtime::alloc(n)
+}
+
+unsafe fn get<'a>(n: *mut c_void) -> &'a BigInt {
+    &*(n as *const BigInt)
+}
+
+// a bigint constant of the compiled code, freed with the rest of the heap
+pub fn constant(n: BigInt) -> *mut c_void {
+    boxed(n)
+}
+
+// natives called by the JIT code, bigints are immutable so every operation allocates a new one
+
+pub extern "C" fn rlan_bigint_from_int(n: i64) -> *mut c_void {
+    boxed(BigInt::from_i64(n))
+}
+
+pub extern "C" fn rlan_bigint_add(a: *mut c_void, b: *mut c_void) -> *mut c_void {
+    unsafe { boxed(get(a).add(get(b))) }
+}
+
+pub extern "C" fn rlan_bigint_sub(a: *mut c_void, b: *mut c_void) -> *mut c_void {
+    unsafe { boxed(get(a).sub(get(b))) }
+}
+
+pub extern "C" fn rlan_bigint_mul(a: *mut c_void, b: *mut c_void) -> *mut c_void {
+    unsafe { boxed(get(a).mul(get(b))) }
+}
+
+pub extern "C" fn rlan_bigint_div(a: *mut c_void, b: *mut c_void) -> *mut c_void {
+    match unsafe { get(a).divmod(get(b)) } {
+        Some((q, _)) => boxed(q),
+        None => runtime::raise(RESULT_DIVISION_BY_ZERO as i64, "division by zero")
+    }
+}
+
+pub extern "C" fn rlan_bigint_rem(a: *mut c_void, b: *mut c_void) -> *mut c_void {
+    match unsafe { get(a).divmod(get(b)) } {
+        Some((_, r)) => boxed(r),
+        None => runtime::raise(RESULT_DIVISION_BY_ZERO as i64, "division by zero")
+    }
+}
+
+// -1, 0 or 1
+pub extern "C" fn rlan_bigint_cmp(a: *mut c_void, b: *mut c_void) -> i64 {
+    unsafe { get(a).cmp(get(b)) as i64 }
+}
+
+pub extern "C" fn rlan_bigint_to_str(a: *mut c_void) -> *mut c_void {
+    runtime::new_str(unsafe { get(a) }.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn big(s: &str) -> BigInt {
+        BigInt::parse(s).unwrap()
+    }
+
+    #[test]
+    fn parse_and_display() {
+        assert_eq!(big("000123").to_string(), "123");
+        assert_eq!(big("-0").to_string(), "0");
+        assert_eq!(big("1000000000000000000000").to_string(), "1000000000000000000000");
+        assert_eq!(big("-1000000000").to_string(), "-1000000000");
+        assert_eq!(BigInt::from_i64(i64::MIN).to_string(), "-9223372036854775808");
+        assert_eq!(BigInt::parse(""), None);
+        assert_eq!(BigInt::parse("-"), None);
+        assert_eq!(BigInt::parse("12a"), None);
+    }
+
+    #[test]
+    fn signs() {
+        assert_eq!(big("5").add(&big("-8")), big("-3"));
+        assert_eq!(big("-5").add(&big("8")), big("3"));
+        assert_eq!(big("-5").sub(&big("-5")).to_string(), "0");
+        assert_eq!(big("-3").mul(&big("4")), big("-12"));
+        assert_eq!(big("-3").mul(&big("-4")), big("12"));
+        assert_eq!(big("0").mul(&big("-4")).to_string(), "0");
+        assert!(big("-18446744073709551616") < big("-1"));
+        assert!(big("-1") < big("0"));
+        assert!(big("18446744073709551616") > big("4294967296"));
+    }
+
+    #[test]
+    fn carry_and_borrow() {
+        assert_eq!(big("4294967295").add(&big("1")), big("4294967296"));
+        assert_eq!(big("18446744073709551615").add(&big("1")), big("18446744073709551616"));
+        assert_eq!(big("4294967296").sub(&big("1")), big("4294967295"));
+        assert_eq!(big("18446744073709551616").sub(&big("1")), big("18446744073709551615"));
+        assert_eq!(big("1").sub(&big("18446744073709551616")), big("-18446744073709551615"));
+        assert_eq!(big("4294967295").mul(&big("4294967295")), big("18446744065119617025"));
+    }
+
+    #[test]
+    fn division() {
+        let n = big("79228162514264337593543950341"); // 2^96 + 5
+        assert_eq!(n.divmod(&big("1")), Some((n.clone(), big("0"))));
+        assert_eq!(n.divmod(&big("18446744073709551616")), Some((big("4294967296"), big("5"))));
+        assert_eq!(big("5").divmod(&big("18446744073709551616")), Some((big("0"), big("5"))));
+        assert_eq!(big("-7").divmod(&big("2")), Some((big("-3"), big("-1"))));
+        assert_eq!(big("7").divmod(&big("-2")), Some((big("-3"), big("1"))));
+        assert_eq!(big("0").divmod(&big("3")), Some((big("0"), big("0"))));
+        assert_eq!(n.divmod(&big("0")), None);
+    }
+}
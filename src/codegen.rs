@@ -1,70 +1,279 @@
-use crate::myast::{Node, Op};
-use crate::wrapper::{Context, Function, Value, Label, Type};
+use crate::myast::{Node, Op, Span, TypeExpr, MethodSig, FmtPart, FmtSpec, Align};
+use crate::wrapper::{Context, Function, Value, Label, Type, RESULT_OK, RESULT_COMPILE_ERROR};
 use std::mem;
-use crate::stdlib::*;
+use crate::stdlib;
+use crate::optimize::{self, OptLevel};
+use crate::runtime::{self, Exception, rlan_exception_new, rlan_exception_catch, rlan_str_len, rlan_str_char_at,
+    rlan_str_byte_at, rlan_char_width};
+use crate::collections::{rlan_vec_new, rlan_vec_len, rlan_vec_get, rlan_vec_set, rlan_vec_push, rlan_vec_pop,
+    rlan_map_new, rlan_map_len, rlan_map_insert, rlan_map_get, rlan_map_contains, rlan_map_remove, rlan_map_keys};
+use crate::bigint::{self, BigInt, rlan_bigint_from_int, rlan_bigint_add, rlan_bigint_sub, rlan_bigint_mul,
+    rlan_bigint_div, rlan_bigint_cmp, rlan_bigint_to_str};
+use crate::format::{rlan_int_to_str, rlan_uint_to_str, rlan_char_to_str, rlan_bool_to_str, rlan_str_pad, rlan_str_concat};
 use either::Either;
 use libc::c_void;
 
+use std::cell::Cell;
 use std::collections::HashMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
 
+// number of calls after which a tiered function is recompiled at the full optimization level.
+// tiering only changes the libjit optimization level: every function is still compiled to
+// native code before its first call, level 0 just compiles faster
+pub const DEFAULT_TIER_THRESHOLD: i64 = 1000;
+
+thread_local! {
+    // the builder whose code is currently running, used by runtime callbacks
+    static ACTIVE: Cell<*mut Builder> = const { Cell::new(ptr::null_mut()) };
+}
+
+#[derive(Clone)]
 pub struct NativeFunc {
     ptr: *mut c_void,
     argtypes: Vec<Type>,
     ret: Type
 }
 
+// call counter of a tiered function, the JIT code increments `calls` directly
+#[repr(C)]
+struct Tier {
+    calls: i64,
+    source: usize, // index of the function in `sources`
+}
+
+// exception handling state of the function being built
+struct Catcher {
+    handler: Value, // id of the innermost try block being executed, -1 outside of them
+    exception: Value, // the thrown object, set before jumping to a catch block
+    tries: Vec<Label>, // catch blocks, indexed by the id of their try
+    current: i64, // id of the try block being built
+}
+
+// the definition of a user function, kept around so it can be rebuilt
+struct FuncSource {
+    func: Function,
+    args: Vec<(String, TypeExpr)>,
+    body: Vec<Node>,
+    tier: Box<Tier>,
+    type_params: HashMap<String, Type>, // type arguments of a generic instance
+    // the functions as they were at the definition, a body built later by a lazy compile
+    // or a tier up doesn't call the redefinitions that came after it
+    callees: HashMap<String, Either<NativeFunc, Function>>,
+}
+
+// a generic function, instantiated for every combination of type arguments it's called with
+#[derive(Clone)]
+struct Generic {
+    params: Vec<String>,
+    args: Vec<(String, TypeExpr)>,
+    rettype: TypeExpr,
+    body: Vec<Node>,
+}
+
 pub struct Builder {
     pub context: Context,
     pub main: Function,
     pub vtable: HashMap<String, Value>,
     pub ftable: HashMap<String, Either<NativeFunc, Function>>,
+    // when set, functions start unoptimized and are recompiled after this many calls
+    pub tier_threshold: Option<i64>,
+    // compile functions on their first call instead of at their definition, `--eager` turns it
+    // off to get the codegen errors of every function up front
+    pub lazy: bool,
+    pub opt_level: OptLevel,
+    // arithmetic overflow throws instead of wrapping around
+    pub checked: bool,
+    // dump the IR of every function to stderr, set by RLAN_DEBUG
+    pub debug: bool,
+    // start of the function being built, target of self tail calls
+    entry: Option<Label>,
+    catcher: Option<Catcher>,
+    // every function defined, a redefinition doesn't replace the old one, whose code can still run
+    sources: Vec<FuncSource>,
+    generics: HashMap<String, Generic>,
+    traits: HashMap<String, Vec<MethodSig>>,
+    // traits and functions implementing methods, by the name of the type and of the method
+    mtable: HashMap<(String, String), (String, String)>,
+    // type arguments of the generic instance being built
+    type_params: HashMap<String, Type>,
+    // folded values of constants
+    consts: HashMap<String, Node>,
+    // addresses and types of global variables
+    globals: HashMap<String, (*mut c_void, Type)>,
 }
 
 impl Builder {
     pub fn new() -> Self {
         let context = Context::new();
-        let main = context.new_function(&mut [Type::void(); 0], Type::int());
-        // initialize built-in functions
+        let main = context.new_function(&[], Type::int());
+        main.set_name("<main>");
+        // initialize built-in functions, module natives are named `module.name`
         let mut ftable : HashMap<String, Either<NativeFunc, Function>> = HashMap::new();
-        ftable.insert(String::from("printint"), 
-            Either::Left(NativeFunc {
-                ptr: stdlib_printint as *mut c_void,
-                argtypes: vec![Type::int()],
-                ret: Type::void()
-            })
-        );
-        Builder {context, main, vtable: HashMap::new(), ftable}
+        let mut modules = stdlib::modules();
+        modules.push(stdlib::prelude());
+        for module in modules {
+            for n in module.natives {
+                let name = if module.name.is_empty() { n.name.to_string() } else { format!("{}.{}", module.name, n.name) };
+                ftable.insert(name, Either::Left(NativeFunc {ptr: n.ptr, argtypes: n.args, ret: n.ret}));
+            }
+        }
+        for (alias, name) in stdlib::aliases() {
+            let entry = ftable[name].clone();
+            ftable.insert(alias.to_string(), entry);
+        }
+        Builder {context, main, vtable: HashMap::new(), ftable, tier_threshold: None, lazy: true, opt_level: OptLevel::O2, checked: cfg!(debug_assertions), debug: false, entry: None, catcher: None, sources: Vec::new(), generics: HashMap::new(), traits: HashMap::new(), mtable: HashMap::new(), type_params: HashMap::new(), consts: HashMap::new(), globals: HashMap::new()}
     }
 
     fn get_type(&self, s: &String) -> Type {
-        if s == "int" {
-            Type::int()
-        } else if s == "bool" {
-            Type::bool()
-        } else if s == "void" {
-            Type::void()
-        } else {
-            panic!("Invalid type")
+        match s.as_str() {
+            "int" => Type::int(),
+            "bool" => Type::bool(),
+            "void" => Type::void(),
+            "unit" => Type::unit(),
+            "str" => Type::str(),
+            "bigint" => Type::bigint(),
+            "char" => Type::char(),
+            "bytes" => Type::bytes(),
+            "file" => Type::file(),
+            "i8" => Type::i8(),
+            "u8" | "byte" => Type::u8(),
+            "i16" => Type::i16(),
+            "u16" => Type::u16(),
+            "i32" => Type::i32(),
+            "u32" => Type::u32(),
+            "i64" => Type::i64(),
+            "u64" => Type::u64(),
+            _ => panic!("Invalid type")
+        }
+    }
+
+    fn resolve_type(&self, t: &TypeExpr) -> Type {
+        match t {
+            TypeExpr::Named(name) => match self.type_params.get(name) {
+                Some(tp) => *tp,
+                None => self.get_type(name)
+            },
+            TypeExpr::Tuple(elems) => {
+                let elems: Vec<Type> = elems.iter().map(|t| self.resolve_type(t)).collect();
+                Type::tuple(&elems)
+            },
+            TypeExpr::Generic(name, args) => {
+                let args: Vec<Type> = args.iter().map(|t| self.resolve_type(t)).collect();
+                match (name.as_str(), args.as_slice()) {
+                    ("Vec", [elem]) => Type::vec(*elem),
+                    ("Map", [key, value]) => Type::map(*key, *value),
+                    _ => panic!("Invalid type")
+                }
+            }
+        }
+    }
+
+    // the name of a type in the source, used to mangle the names of generic instances and methods
+    fn type_name(&self, tp: &Type) -> String {
+        if tp.is_tuple() {
+            let elems: Vec<String> = (0..tp.field_count()).map(|i| self.type_name(&tp.field(i))).collect();
+            return format!("({})", elems.join(", "));
+        }
+        if tp.is_vec() {
+            return format!("Vec[{}]", self.type_name(&tp.elem()));
+        }
+        if tp.is_map() {
+            return format!("Map[{}, {}]", self.type_name(&tp.key()), self.type_name(&tp.value()));
         }
+        let names = ["int", "bool", "void", "unit", "str", "bigint", "char", "bytes", "file",
+            "i8", "u8", "i16", "u16", "i32", "u32", "i64", "u64"];
+        names.iter().find(|name| self.get_type(&name.to_string()).same_as(tp))
+            .expect("Type without a name").to_string()
     }
 
-    pub fn execute(&mut self) -> i32 {
-        self.main.compile();
+    // generate the top level code, its last value is the result of `execute`
+    pub fn build_main(&mut self, code: &Vec<Node>) {
+        self.begin_catcher(code);
+        let mut val = Value::constant_long(&self.main, 0);
+        for n in code {
+            val = self.visit(n);
+        }
+        // the result is the exit status, scripts which don't end with an int succeed
+        if !val.get_type().is_int() {
+            val = Value::constant_long(&self.main, 0);
+        }
+        self.main.i_return(&val);
+        self.finish_catcher();
+    }
+
+    pub fn execute(&mut self) -> Result<i64, Box<Exception>> {
+        self.main.compile_at(self.opt_level.jit_level());
+        self.context.finish();
+        runtime::install();
+        let prev = ACTIVE.with(|a| a.replace(self as *mut Builder));
+        let res = self.main.standard_execute();
+        ACTIVE.with(|a| a.set(prev));
+        runtime::free_heap();
+        res.ok_or_else(|| runtime::take_uncaught().expect("Execution failed without an exception"))
+    }
+
+    // build a lazily compiled function, the context is already locked by libjit
+    fn compile_on_demand(&mut self, func: *mut c_void) -> bool {
+        let source = match self.sources.iter().position(|src| src.func.ptr as *mut c_void == func) {
+            Some(source) => source,
+            None => return false
+        };
+        self.build_function(source, self.tier_threshold.is_none());
+        true
+    }
+
+    // names of the functions an exception was thrown through and the source offsets of the calls
+    pub fn stack_trace(&self, exc: &Exception) -> Vec<(String, Option<usize>)> {
+        exc.trace.frames(&self.context).iter()
+            .map(|(func, offset)| (func.name().unwrap_or(String::from("<unknown>")), *offset))
+            .collect()
+    }
+
+    // recompile a function built at level 0 at the full level, libjit redirects the calls made
+    // from now on, the calls already running, including the one that got hot, finish in the old code
+    fn tier_up(&mut self, source: usize) {
+        self.context.start();
+        self.build_function(source, true);
         self.context.finish();
-        self.main.standard_execute()
     }
 
     pub fn visit(&mut self, n: &Node) -> Value {
         match n {
+            Node::Unit => Value::constant_unit(&self.main),
             Node::Number(i) => self.visit_number(i),
-            Node::BinOp(lhs, op, rhs) => self.visit_binop(lhs, op, rhs),
+            Node::Bool(b) => self.visit_bool(*b),
+            Node::BigNumber(digits) => self.visit_bignumber(digits),
+            Node::TypedNumber(n, suffix) => self.visit_typed_number(*n, suffix),
+            Node::StrLiteral(s) => self.visit_str(s),
+            Node::CharLiteral(c) => self.visit_char(*c),
+            Node::BinOp(lhs, op, rhs, span) => self.visit_binop(lhs, op, rhs, span),
             Node::FuncDef(name, args, rettype, body) => self.visit_funcdef(name, args, rettype, body),
+            Node::GenericDef(name, params, args, rettype, body) => self.visit_genericdef(name, params, args, rettype, body),
+            Node::Trait(name, methods) => self.visit_trait(name, methods),
+            Node::Impl(name, tp, methods) => self.visit_impl(name, tp, methods),
+            Node::MethodCall(recv, method, args, span) => self.visit_method_call(recv, method, args, span),
             Node::VarDef(name, val) => self.visit_vardef(name, val),
+            Node::Const(name, val) => self.visit_const(name, val),
+            Node::Global(name, val) => self.visit_global(name, val),
+            Node::Assign(name, val) => self.visit_assign(name, val),
             Node::Ident(name) => self.visit_ident(name),
-            Node::Call(name_and_args) => self.visit_call(name_and_args),
+            Node::Call(name_and_args, span) => self.visit_call(name_and_args, span),
             Node::If(cond, then, other) => self.visit_if(cond, then, other),
             Node::Ret(val) => self.visit_ret(val),
-            _ => unimplemented!()
+            Node::Raise(val, span) => self.visit_raise(val, span),
+            Node::Try(body, name, handler) => self.visit_try(body, name, handler),
+            Node::Cast(val, typename, span) => self.visit_cast(val, typename, span),
+            Node::For(name, iter, body) => self.visit_for(name, iter, body),
+            Node::Tuple(elems) => self.visit_tuple(elems),
+            Node::VecLit(elems) => self.visit_vec(elems, None),
+            Node::MapLit(entries) => self.visit_map(entries, None),
+            Node::Format(parts, span) => self.visit_format(parts, &[], span),
+            Node::Destructure(names, val) => self.visit_destructure(names, val),
+            Node::Empty => Value::constant_unit(&self.main),
+            // main stops after the syntax errors and only `rlan fmt` keeps the trivia
+            Node::Error => panic!("Can't compile code with a syntax error"),
+            Node::Trivia(_) => panic!("Can't compile comments and blank lines"),
         }
     }
 
@@ -72,107 +281,986 @@ impl Builder {
         Value::constant_long(&self.main, *i)
     }
 
+    fn visit_bool(&mut self, b: bool) -> Value {
+        Value::constant(&self.main, Type::bool(), b as i64)
+    }
+
+    fn visit_bignumber(&mut self, digits: &String) -> Value {
+        let n = BigInt::parse(digits).expect("Invalid integer literal");
+        Value::constant(&self.main, Type::bigint(), bigint::constant(n) as i64)
+    }
+
+    fn visit_typed_number(&mut self, n: u64, suffix: &String) -> Value {
+        let fits = match suffix.as_str() {
+            "i8" => n <= i8::MAX as u64,
+            "u8" => n <= u8::MAX as u64,
+            "i16" => n <= i16::MAX as u64,
+            "u16" => n <= u16::MAX as u64,
+            "i32" => n <= i32::MAX as u64,
+            "u32" => n <= u32::MAX as u64,
+            "i64" => n <= i64::MAX as u64,
+            _ => true
+        };
+        if !fits {
+            panic!("Integer literal {}{} is out of range", n, suffix);
+        }
+        Value::constant(&self.main, self.get_type(suffix), n as i64)
+    }
+
+    fn visit_str(&mut self, s: &String) -> Value {
+        Value::constant(&self.main, Type::str(), runtime::new_str(s.clone()) as i64)
+    }
+
+    fn visit_char(&mut self, c: char) -> Value {
+        Value::constant(&self.main, Type::char(), c as i64)
+    }
+
+    fn is_variable(&self, name: &String) -> bool {
+        self.vtable.contains_key(name) || self.consts.contains_key(name) || self.globals.contains_key(name)
+    }
+
+    // locals shadow constants, which shadow globals
     fn visit_ident(&mut self, name: &String) -> Value {
-        let ptr = self.vtable.get(name).expect("Variable doesn't exist.");
-        self.main.i_load(ptr)
+        if let Some(ptr) = self.vtable.get(name) {
+            return self.main.i_load(ptr);
+        }
+        if let Some(val) = self.consts.get(name) {
+            return self.visit(&val.clone());
+        }
+        let (addr, tp) = *self.globals.get(name).expect("Variable doesn't exist.");
+        let addr = Value::constant_ptr(&self.main, addr);
+        self.main.i_load_relative(&addr, 0, tp)
     }
 
     fn visit_ret(&mut self, val: &Box<Node>) -> Value {
-        let cval = self.visit(&*val);
+        // top level code has no frame to reuse and a catcher needs the frame
+        if self.entry.is_some() && self.catcher.is_none() {
+            let call = match &**val {
+                Node::Call(name_and_args, span) => self.call_args(name_and_args).map(|(fname, vals)| (fname, vals, span)),
+                Node::MethodCall(recv, method, args, span) => {
+                    let (fname, vals) = self.method_call_args(recv, method, args);
+                    Some((fname, vals, span))
+                },
+                _ => None
+            };
+            if let Some((fname, vals, span)) = call {
+                return self.tail_call(&fname, vals, span);
+            }
+        }
+        let cval = self.visit(val);
         self.main.i_return(&cval);
         Value::constant_long(&self.main, 0) // type assured within the call
     }
 
-    fn visit_binop(&mut self, lhs: &Node, op: &Op, rhs: &Node) -> Value {
+    // calls in tail position don't grow the stack: self calls jump back to the entry
+    // and calls to functions with the same signature reuse the caller's frame. the callee
+    // is resolved like in `call`, so generic instances and methods are included
+    fn tail_call(&mut self, fname: &String, vals: Vec<Value>, span: &Span) -> Value {
+        let fname = self.resolve(fname, &vals);
+        let callee = match self.ftable.get(&fname) {
+            // a wrong number of arguments is reported by the normal call
+            Some(Either::Right(f)) if f.argc() == vals.len() => *f,
+            _ => {
+                let res = self.call(&fname, vals, span);
+                self.main.i_return(&res);
+                return Value::constant_long(&self.main, 0);
+            }
+        };
+        if callee.ptr == self.main.ptr {
+            return self.recur(vals);
+        }
+        let res = if callee.same_signature(&self.main) {
+            self.main.i_mark_offset(span.lo);
+            self.main.i_tail_call(&callee, vals.as_ref())
+        } else {
+            self.call(&fname, vals, span)
+        };
+        self.main.i_return(&res);
+        Value::constant_long(&self.main, 0)
+    }
+
+    // the arguments are all evaluated before any parameter is overwritten
+    fn recur(&mut self, vals: Vec<Value>) -> Value {
+        let params = self.main.get_params();
+        for i in 0..params.len() {
+            self.main.i_store(&vals[i], &params[i]);
+        }
+        self.main.i_branch(self.entry.as_ref().expect("Tail call outside of a function"));
+        Value::constant_long(&self.main, 0) // never used, the branch doesn't return
+    }
+
+    fn visit_binop(&mut self, lhs: &Node, op: &Op, rhs: &Node, span: &Span) -> Value {
         let lhs = self.visit(lhs);
         let rhs = self.visit(rhs);
         if lhs.get_type().is_int() && rhs.get_type().is_int() {
+            let (lhs, rhs, tp) = self.unify_ints(lhs, rhs);
             match op {
+                Op::Add | Op::Sub | Op::Mul | Op::Div => {
+                    self.main.i_mark_offset(span.lo);
+                },
+                _ => {}
+            }
+            // libjit picks the signed or unsigned instruction from the operand types
+            let res = match op {
+                Op::Add if self.checked => self.main.i_add_ovf(&lhs, &rhs),
+                Op::Sub if self.checked => self.main.i_sub_ovf(&lhs, &rhs),
+                Op::Mul if self.checked => self.main.i_mul_ovf(&lhs, &rhs),
                 Op::Add => self.main.i_add(&lhs, &rhs),
                 Op::Sub => self.main.i_sub(&lhs, &rhs),
                 Op::Mul => self.main.i_mul(&lhs, &rhs),
-                Op::Eql => self.main.i_convert(&self.main.i_eq(&lhs, &rhs), Type::bool()),
-                Op::Neq => self.main.i_convert(&self.main.i_ne(&lhs, &rhs), Type::bool()),
-                Op::Lwt => self.main.i_convert(&self.main.i_lt(&lhs, &rhs), Type::bool()),
-                Op::Lwe => self.main.i_convert(&self.main.i_le(&lhs, &rhs), Type::bool()),
-                Op::Grt => self.main.i_convert(&self.main.i_gt(&lhs, &rhs), Type::bool()),
-                Op::Gre => self.main.i_convert(&self.main.i_ge(&lhs, &rhs), Type::bool()),
+                Op::Div => self.main.i_div(&lhs, &rhs),
+                Op::Eql => return self.main.i_to_bool(&self.main.i_eq(&lhs, &rhs)),
+                Op::Neq => return self.main.i_to_bool(&self.main.i_ne(&lhs, &rhs)),
+                Op::Lwt => return self.main.i_to_bool(&self.main.i_lt(&lhs, &rhs)),
+                Op::Lwe => return self.main.i_to_bool(&self.main.i_le(&lhs, &rhs)),
+                Op::Grt => return self.main.i_to_bool(&self.main.i_gt(&lhs, &rhs)),
+                Op::Gre => return self.main.i_to_bool(&self.main.i_ge(&lhs, &rhs)),
+                _ => panic!("Invalid binary operands")
+            };
+            self.narrow(res, tp, self.checked)
+        } else if (lhs.get_type().is_bigint() || lhs.get_type().is_int()) &&
+            (rhs.get_type().is_bigint() || rhs.get_type().is_int()) {
+            self.bigint_binop(lhs, op, rhs, span)
+        } else if lhs.get_type().is_char() && rhs.get_type().is_char() {
+            // chars are compared by their code
+            match op {
+                Op::Eql => self.main.i_to_bool(&self.main.i_eq(&lhs, &rhs)),
+                Op::Neq => self.main.i_to_bool(&self.main.i_ne(&lhs, &rhs)),
+                Op::Lwt => self.main.i_to_bool(&self.main.i_lt(&lhs, &rhs)),
+                Op::Lwe => self.main.i_to_bool(&self.main.i_le(&lhs, &rhs)),
+                Op::Grt => self.main.i_to_bool(&self.main.i_gt(&lhs, &rhs)),
+                Op::Gre => self.main.i_to_bool(&self.main.i_ge(&lhs, &rhs)),
                 _ => panic!("Invalid binary operands")
             }
         } else if lhs.get_type().is_bool() && rhs.get_type().is_bool() {
             match op {
-                Op::And => self.main.i_and(&lhs, &rhs),
-                Op::Or => self.main.i_or(&lhs, &rhs),
+                Op::And => self.main.i_to_bool(&self.main.i_and(&lhs, &rhs)),
+                Op::Or => self.main.i_to_bool(&self.main.i_or(&lhs, &rhs)),
                 _ => panic!("Invalid binary operands")
             }
         } else {
-            print!("Invalid binary operands for operator {:?}", op);
+            eprint!("Invalid binary operands for operator {:?}", op);
             lhs.get_type().dump(); rhs.get_type().dump();
-            println!("");
+            eprintln!();
             panic!("Err")
         }
     }
 
-    fn visit_funcdef(&mut self, name: &String, args: &Vec<(String, String)>, rettype: &String, body: &Vec<Node>) -> Value {
+    // both operands get the same type, an untyped constant takes the type of the other side
+    fn unify_ints(&mut self, lhs: Value, rhs: Value) -> (Value, Value, Type) {
+        let (lt, rt) = (lhs.get_type(), rhs.get_type());
+        if lt.same_as(&rt) {
+            (lhs, rhs, lt)
+        } else if rhs.is_constant() {
+            (lhs, self.convert(&rhs, lt), lt)
+        } else if lhs.is_constant() {
+            (self.convert(&lhs, rt), rhs, rt)
+        } else {
+            panic!("Mismatched integer types, use `as` to convert");
+        }
+    }
+
+    // arithmetic on types smaller than int is done on ints, the result is converted back
+    fn narrow(&mut self, val: Value, tp: Type, checked: bool) -> Value {
+        if val.get_type().same_as(&tp) {
+            val
+        } else if checked {
+            self.main.i_convert_ovf(&val, tp)
+        } else {
+            self.main.i_convert(&val, tp)
+        }
+    }
+
+    fn convert(&mut self, val: &Value, tp: Type) -> Value {
+        if self.checked {
+            self.main.i_convert_ovf(val, tp)
+        } else {
+            self.main.i_convert(val, tp)
+        }
+    }
+
+    // int to bigint conversion, the value is widened to int first
+    fn promote(&mut self, val: Value) -> Value {
+        if !val.get_type().is_int() {
+            return val;
+        }
+        let val = if val.get_type().same_as(&Type::int()) { val } else { self.convert(&val, Type::int()) };
+        self.main.i_native_call(rlan_bigint_from_int as *mut c_void, &[val], Type::bigint())
+    }
+
+    fn visit_cast(&mut self, val: &Box<Node>, typename: &TypeExpr, span: &Span) -> Value {
+        let to = self.resolve_type(typename);
+        // the type of collection literals can be given with `as`, which is the only way for empty ones
+        match &**val {
+            Node::VecLit(elems) if to.is_vec() => return self.visit_vec(elems, Some(to)),
+            Node::MapLit(entries) if to.is_map() => return self.visit_map(entries, Some(to)),
+            _ => {}
+        }
+        let val = self.visit(val);
+        let from = val.get_type();
+        if from.same_as(&to) {
+            val
+        } else if from.is_int() && to.is_int() {
+            self.main.i_mark_offset(span.lo);
+            self.convert(&val, to)
+        } else if from.is_bool() && to.is_int() {
+            self.main.i_convert(&val, to)
+        } else if from.is_int() && to.is_bigint() {
+            self.main.i_mark_offset(span.lo);
+            self.promote(val)
+        } else {
+            panic!("Invalid cast to {}", self.type_name(&to));
+        }
+    }
+
+    // at least one of the operands is a bigint, ints are promoted
+    fn bigint_binop(&mut self, lhs: Value, op: &Op, rhs: Value, span: &Span) -> Value {
+        let lhs = self.promote(lhs);
+        let rhs = self.promote(rhs);
+        let arith = |f: &Function, native: *mut c_void| f.i_native_call(native, &[lhs, rhs], Type::bigint());
+        self.main.i_mark_offset(span.lo);
+        match op {
+            Op::Add => return arith(&self.main, rlan_bigint_add as *mut c_void),
+            Op::Sub => return arith(&self.main, rlan_bigint_sub as *mut c_void),
+            Op::Mul => return arith(&self.main, rlan_bigint_mul as *mut c_void),
+            Op::Div => return arith(&self.main, rlan_bigint_div as *mut c_void),
+            _ => {}
+        }
+        // comparisons compare the result of `rlan_bigint_cmp` with zero
+        let ord = self.main.i_native_call(rlan_bigint_cmp as *mut c_void, &[lhs, rhs], Type::int());
+        let zero = Value::constant_long(&self.main, 0);
+        let cmp = match op {
+            Op::Eql => self.main.i_eq(&ord, &zero),
+            Op::Neq => self.main.i_ne(&ord, &zero),
+            Op::Lwt => self.main.i_lt(&ord, &zero),
+            Op::Lwe => self.main.i_le(&ord, &zero),
+            Op::Grt => self.main.i_gt(&ord, &zero),
+            Op::Gre => self.main.i_ge(&ord, &zero),
+            _ => panic!("Invalid binary operands")
+        };
+        self.main.i_to_bool(&cmp)
+    }
+
+    fn visit_funcdef(&mut self, name: &String, args: &Vec<(String, TypeExpr)>, rettype: &TypeExpr, body: &Vec<Node>) -> Value {
+        self.define_function(name, args, rettype, body, HashMap::new());
+        Value::constant_unit(&self.main)
+    }
+
+    // generic functions are only built when they're called, see `instantiate`
+    fn visit_genericdef(&mut self, name: &String, params: &Vec<String>, args: &Vec<(String, TypeExpr)>, rettype: &TypeExpr, body: &Vec<Node>) -> Value {
+        self.generics.insert(name.clone(), Generic {
+            params: params.clone(),
+            args: args.clone(),
+            rettype: rettype.clone(),
+            body: body.clone(),
+        });
+        Value::constant_unit(&self.main)
+    }
+
+    // the name of the instance of a generic function for the argument types, built on the first call
+    fn instantiate(&mut self, name: &String, args: &[Value]) -> String {
+        let generic = self.generics[name].clone();
+        if generic.args.len() != args.len() {
+            panic!("Invalid arguments for {}", name);
+        }
+        let mut bindings = HashMap::new();
+        for ((_, argtype), val) in generic.args.iter().zip(args.iter()) {
+            infer(&generic.params, argtype, val.get_type(), &mut bindings);
+        }
+        let mut types = Vec::new();
+        for param in &generic.params {
+            match bindings.get(param) {
+                Some(tp) => types.push(self.type_name(tp)),
+                None => panic!("Can't infer the type parameter {} of {}", param, name)
+            }
+        }
+        let mangled = format!("{}[{}]", name, types.join(", "));
+        if !self.ftable.contains_key(&mangled) {
+            self.define_function(&mangled, &generic.args, &generic.rettype, &generic.body, bindings);
+        }
+        mangled
+    }
+
+    fn visit_trait(&mut self, name: &String, methods: &Vec<MethodSig>) -> Value {
+        self.traits.insert(name.clone(), methods.clone());
+        Value::constant_unit(&self.main)
+    }
+
+    // the methods are functions named `type.method`, registered in the method table
+    fn visit_impl(&mut self, name: &String, tp: &TypeExpr, methods: &Vec<Node>) -> Value {
+        let sigs = self.traits.get(name).unwrap_or_else(|| panic!("Trait {} doesn't exist", name)).clone();
+        let tname = self.type_name(&self.resolve_type(tp));
+        for (method, args, rettype) in &sigs {
+            let def = methods.iter().find(|m| match m {
+                Node::FuncDef(mname, ..) => mname == method,
+                _ => false
+            });
+            let (margs, mrettype, body) = match def {
+                Some(Node::FuncDef(_, margs, mrettype, body)) => (margs, mrettype, body),
+                _ => panic!("Method {} of trait {} isn't implemented for {}", method, name, tname)
+            };
+            // the trait's signature, with `self` of the implementing type
+            let expected: Vec<Type> = args.iter().map(|(_, t)| self.resolve_type(t)).collect();
+            let found: Vec<Type> = margs[1..].iter().map(|(_, t)| self.resolve_type(t)).collect();
+            if expected.len() != found.len() || !expected.iter().zip(found.iter()).all(|(a, b)| a.same_as(b)) ||
+                !self.resolve_type(rettype).same_as(&self.resolve_type(mrettype)) {
+                panic!("Method {} of {} for {} doesn't match the trait", method, name, tname);
+            }
+            // a method call couldn't tell which trait's method it is
+            if let Some((other, _)) = self.mtable.get(&(tname.clone(), method.clone())) {
+                panic!("Method {} of {} for {} is already implemented by trait {}", method, name, tname, other);
+            }
+            // registered first, the body can call the method itself
+            let fname = format!("{}.{}", tname, method);
+            self.mtable.insert((tname.clone(), method.clone()), (name.clone(), fname.clone()));
+            self.define_function(&fname, margs, mrettype, body, HashMap::new());
+        }
+        for m in methods {
+            if let Node::FuncDef(mname, ..) = m {
+                if !sigs.iter().any(|(method, _, _)| method == mname) {
+                    panic!("{} is not a method of trait {}", mname, name);
+                }
+            }
+        }
+        Value::constant_unit(&self.main)
+    }
+
+    fn define_function(&mut self, name: &String, args: &Vec<(String, TypeExpr)>, rettype: &TypeExpr, body: &Vec<Node>,
+        type_params: HashMap<String, Type>) {
+        let pre_params = mem::replace(&mut self.type_params, type_params.clone());
         // get argument types
         let mut argtypes : Vec<Type> = Vec::new();
-        for (_, argtype) in args {
-            argtypes.push(self.get_type(&argtype));
+        for (arg, argtype) in args {
+            let tp = self.resolve_type(argtype);
+            // `f ()` is a call without arguments, so a unit argument couldn't be passed
+            if tp.is_unit() {
+                panic!("Parameter {} of {} can't be of type unit", arg, name);
+            }
+            argtypes.push(tp);
         }
         // create the function
-        let func = self.context.new_function(argtypes.as_mut(), self.get_type(rettype));
+        let func = self.context.new_function(argtypes.as_mut(), self.resolve_type(rettype));
+        self.type_params = pre_params;
+        func.set_name(name);
+        if self.tier_threshold.is_some() {
+            func.set_recompilable();
+        }
+        // save it in case of recursion
+        self.ftable.insert(name.clone(),
+            Either::Right(func), // right = custom function
+        );
+        let source = self.sources.len();
+        self.sources.push(FuncSource {
+            func,
+            args: args.clone(),
+            body: body.clone(),
+            tier: Box::new(Tier {calls: 0, source}),
+            type_params,
+            callees: self.ftable.clone(),
+        });
+        if self.lazy {
+            func.set_on_demand_compiler(compile_on_demand);
+        } else {
+            // tiered functions are compiled at level 0 and recompiled once they're hot
+            self.build_function(source, self.tier_threshold.is_none());
+        }
+    }
+
+    // generate the body of a defined function and compile it
+    fn build_function(&mut self, source: usize, optimized: bool) {
+        let (func, args, body, type_params, callees) = {
+            let src = &self.sources[source];
+            (src.func, src.args.clone(), src.body.clone(), src.type_params.clone(), src.callees.clone())
+        };
+        let pre_ftable = mem::replace(&mut self.ftable, callees);
         // place it instead of main
         let pre_main = mem::replace(&mut self.main, func);
-        // clear the symtable
-        self.vtable.clear();
+        let pre_catcher = self.catcher.take();
+        let pre_params = mem::replace(&mut self.type_params, type_params);
+        // clear the symtable, generic instances can be built in the middle of another function
+        let pre_vtable = mem::take(&mut self.vtable);
         // load parameters
         let params = self.main.get_params();
         for i in 0..params.len() {
             let param = params[i];
             self.vtable.insert(args[i].0.clone(), param);
         }
-        // and save it in case of recursion
-        self.ftable.insert(name.clone(),
-            Either::Right(self.main), // right = custom function
-        );
+        self.begin_catcher(&body);
+        if !optimized {
+            self.count_call(source);
+        }
+        // self tail calls jump here, after the call was counted
+        let entry = Label::new();
+        entry.place(&self.main);
+        let pre_entry = self.entry.replace(entry);
         // compile body
-        for n in body {
+        for n in &body {
             self.visit(n);
         }
-        #[cfg(debug_assertions)]
-        self.main.dump();
-        self.main.compile();
-        self.main.dump();
+        self.finish_catcher();
+        if self.debug {
+            self.main.dump();
+        }
+        if optimized {
+            self.main.compile_at(self.opt_level.jit_level());
+        } else {
+            self.main.compile_at(0);
+        }
         // place main again
-        mem::replace(&mut self.main, pre_main);
-        Value::constant_void(&self.main)
+        self.main = pre_main;
+        self.entry = pre_entry;
+        self.catcher = pre_catcher;
+        self.type_params = pre_params;
+        self.vtable = pre_vtable;
+        // the generic instances built for the body are kept
+        for (name, func) in mem::replace(&mut self.ftable, pre_ftable) {
+            self.ftable.entry(name).or_insert(func);
+        }
+    }
+
+    // functions containing a try need a catcher set up before anything can throw
+    fn begin_catcher(&mut self, body: &[Node]) {
+        if !body.iter().any(has_try) {
+            return;
+        }
+        self.main.i_uses_catcher();
+        let handler = Value::create(&self.main, &Type::int());
+        self.main.i_store(&Value::constant_long(&self.main, -1), &handler);
+        let exception = Value::create(&self.main, &Type::void_ptr());
+        self.catcher = Some(Catcher {handler, exception, tries: Vec::new(), current: -1});
+    }
+
+    // dispatch thrown exceptions to the catch block of the try they were thrown in
+    fn finish_catcher(&mut self) {
+        let catcher = match self.catcher.take() {
+            Some(c) => c,
+            None => return
+        };
+        self.main.i_default_return();
+        let exception = self.main.i_start_catcher();
+        self.main.i_store(&exception, &catcher.exception);
+        for (id, lbl) in catcher.tries.iter().enumerate() {
+            let is_id = self.main.i_eq(&catcher.handler, &Value::constant_long(&self.main, id as i64));
+            self.main.i_branch_if(&is_id, lbl);
+        }
+        self.main.i_rethrow_unhandled();
+    }
+
+    // increment the call counter and tier up when it reaches the threshold
+    fn count_call(&mut self, source: usize) {
+        let tier = &*self.sources[source].tier as *const Tier as *mut c_void;
+        let tier = Value::constant_ptr(&self.main, tier);
+        let calls = self.main.i_load_relative(&tier, 0, Type::int());
+        let calls = self.main.i_add(&calls, &Value::constant_long(&self.main, 1));
+        self.main.i_store_relative(&tier, 0, &calls);
+        let threshold = Value::constant_long(&self.main, self.tier_threshold.unwrap());
+        let cold = Label::new();
+        self.main.i_branch_if_not(&self.main.i_eq(&calls, &threshold), &cold);
+        self.main.i_native_call(tier_up as *mut c_void, &[tier], Type::void());
+        cold.place(&self.main);
     }
 
-    fn visit_call(&mut self, name_and_args: &Vec<Node>) -> Value {
+    fn visit_call(&mut self, name_and_args: &Vec<Node>, span: &Span) -> Value {
+        match self.call_args(name_and_args) {
+            Some((fname, args)) => self.call(&fname, args, span),
+            None => self.visit_format_call(&name_and_args[1..], span)
+        }
+    }
+
+    // the name of the called function and the values of the arguments, None for `format`
+    fn call_args(&mut self, name_and_args: &Vec<Node>) -> Option<(String, Vec<Value>)> {
         let fname = match &name_and_args[0] {
             Node::Ident(id) => id,
             _ => panic!("Function name must be an identifier")
         };
+        if fname == "format" && !self.ftable.contains_key(fname) {
+            return None;
+        }
         let mut args : Vec<Value> = Vec::new();
         for i in 1..name_and_args.len() {
             args.push(self.visit(name_and_args.get(i).unwrap()));
         };
+        Some((fname.clone(), args))
+    }
+
+    fn visit_method_call(&mut self, recv: &Box<Node>, method: &String, args: &Vec<Node>, span: &Span) -> Value {
+        let (fname, vals) = self.method_call_args(recv, method, args);
+        self.call(&fname, vals, span)
+    }
+
+    // methods are resolved statically from the type of the receiver,
+    // without a method of that name `x.f(a, b)` is the function call `f x a b`
+    fn method_call_args(&mut self, recv: &Box<Node>, method: &String, args: &Vec<Node>) -> (String, Vec<Value>) {
+        // `module.name(args)` calls a native of a module, unless the module name is a variable
+        if let Node::Ident(module) = &**recv {
+            let fname = format!("{}.{}", module, method);
+            if self.ftable.contains_key(&fname) && !self.is_variable(module) {
+                let vals: Vec<Value> = args.iter().map(|a| self.visit(a)).collect();
+                return (fname, vals);
+            }
+        }
+        let mut vals = vec![self.visit(recv)];
+        for a in args {
+            vals.push(self.visit(a));
+        }
+        let tname = self.type_name(&vals[0].get_type());
+        let fname = match self.mtable.get(&(tname, method.clone())) {
+            Some((_, fname)) => fname.clone(),
+            None => method.clone()
+        };
+        (fname, vals)
+    }
+
+    // the instance of a generic function for the argument types, or the name itself
+    fn resolve(&mut self, fname: &String, args: &[Value]) -> String {
+        if !self.ftable.contains_key(fname) && self.generics.contains_key(fname) {
+            self.instantiate(fname, args)
+        } else {
+            fname.clone()
+        }
+    }
+
+    fn call(&mut self, fname: &String, args: Vec<Value>, span: &Span) -> Value {
+        let fname = &self.resolve(fname, &args);
+        self.main.i_mark_offset(span.lo);
         let func = match self.ftable.get(fname) {
-            None => panic!("Function doesn't exist"),
+            None => return self.visit_intrinsic(fname, &args)
+                .unwrap_or_else(|| panic!("Function doesn't exist")),
             Some(f) => f
         };
-        match &func {
+        let (res, ret) = match &func {
             Either::Left(nativefunc) => {
-                self.main.i_native_call(nativefunc.ptr, args.as_ref(), nativefunc.ret)
+                if nativefunc.argtypes.len() != args.len() ||
+                    !nativefunc.argtypes.iter().zip(args.iter()).all(|(t, a)| t.same_as(&a.get_type())) {
+                    panic!("Invalid arguments for {}", fname);
+                }
+                (self.main.i_native_call(nativefunc.ptr, args.as_ref(), nativefunc.ret), nativefunc.ret)
             },
-            Either::Right(codefunc) => self.main.i_normal_call(codefunc, args.as_ref())
+            Either::Right(codefunc) => {
+                if codefunc.argc() != args.len() {
+                    panic!("Invalid arguments for {}", fname);
+                }
+                (self.main.i_normal_call(codefunc, args.as_ref()), codefunc.return_type())
+            }
+        };
+        // calls of void functions are statements
+        if ret.is_void() {
+            Value::constant_unit(&self.main)
+        } else {
+            res
+        }
+    }
+
+    // builtins which are compiled to instructions instead of calls
+    fn visit_intrinsic(&mut self, name: &String, args: &Vec<Value>) -> Option<Value> {
+        let op: fn(&Function, &Value, &Value) -> Value = match name.as_str() {
+            "wrapping_add" => Function::i_add,
+            "wrapping_sub" => Function::i_sub,
+            "wrapping_mul" => Function::i_mul,
+            "len" | "push" | "pop" | "get" | "set" | "insert" | "remove" | "contains" => {
+                return Some(self.collection_op(name, args));
+            },
+            "to_str" if args.len() == 1 => return Some(self.str_of(&args[0], 10, false)),
+            _ => return None
+        };
+        if args.len() != 2 || !args[0].get_type().is_int() || !args[1].get_type().is_int() {
+            panic!("{} expects two ints", name);
+        }
+        let (lhs, rhs, tp) = self.unify_ints(args[0], args[1]);
+        let res = op(&self.main, &lhs, &rhs);
+        Some(self.narrow(res, tp, false))
+    }
+
+    // `format "{} = {:5}" a b`, the `{}` placeholders of the literal are filled by the other arguments
+    fn visit_format_call(&mut self, args: &[Node], span: &Span) -> Value {
+        let parts = match args.first() {
+            Some(Node::Format(parts, _)) => parts.clone(),
+            Some(Node::StrLiteral(s)) => vec![FmtPart::Lit(s.clone())],
+            _ => panic!("The first argument of format must be a str literal")
+        };
+        let vals: Vec<Value> = args[1..].iter().map(|a| self.visit(a)).collect();
+        self.visit_format(&parts, &vals, span)
+    }
+
+    // the parts converted to strs and concatenated
+    fn visit_format(&mut self, parts: &Vec<FmtPart>, args: &[Value], span: &Span) -> Value {
+        let mut next = 0;
+        let mut used = vec![false; args.len()];
+        let mut res: Option<Value> = None;
+        for part in parts {
+            let s = match part {
+                FmtPart::Lit(s) => self.visit_str(s),
+                FmtPart::Arg(val, spec) => {
+                    let val = self.visit(val);
+                    self.format_value(&val, spec)
+                },
+                FmtPart::Pos(pos, spec) => {
+                    let i = pos.unwrap_or_else(|| {
+                        next += 1;
+                        next - 1
+                    });
+                    let val = match args.get(i) {
+                        Some(val) => *val,
+                        None => panic!("No argument of format for the placeholder {}", i)
+                    };
+                    used[i] = true;
+                    self.format_value(&val, spec)
+                }
+            };
+            res = Some(match res {
+                None => s,
+                Some(prev) => {
+                    self.main.i_mark_offset(span.lo);
+                    self.main.i_native_call(rlan_str_concat as *mut c_void, &[prev, s], Type::str())
+                }
+            });
         }
+        if used.contains(&false) {
+            panic!("Unused arguments of format");
+        }
+        res.unwrap_or_else(|| self.visit_str(&String::new()))
+    }
+
+    // the text of a value, with the width, alignment and precision of the spec
+    fn format_value(&mut self, val: &Value, spec: &FmtSpec) -> Value {
+        let tp = val.get_type();
+        let number = tp.is_int() || tp.is_bigint();
+        if spec.radix != 10 && !tp.is_int() {
+            panic!("Only fixed-size integers can be formatted in another radix");
+        }
+        let s = self.str_of(val, spec.radix, spec.upper);
+        if spec.width == 0 && spec.precision.is_none() {
+            return s;
+        }
+        let align = match spec.align {
+            Some(align) => align,
+            None if number => Align::Right,
+            None => Align::Left
+        };
+        let precision = match spec.precision {
+            Some(_) if number => panic!("A precision can't be applied to an integer"),
+            Some(precision) => precision as i64,
+            None => -1
+        };
+        let args = [
+            s,
+            Value::constant(&self.main, Type::char(), spec.fill as i64),
+            Value::constant(&self.main, Type::int(), align as i64),
+            Value::constant(&self.main, Type::int(), spec.width as i64),
+            Value::constant(&self.main, Type::int(), precision),
+            Value::constant(&self.main, Type::bool(), (spec.zero && number) as i64),
+        ];
+        self.main.i_native_call(rlan_str_pad as *mut c_void, &args, Type::str())
+    }
+
+    // `to_str x` for every type with a text
+    fn str_of(&mut self, val: &Value, radix: u32, upper: bool) -> Value {
+        let tp = val.get_type();
+        let native = if tp.is_str() {
+            return *val;
+        } else if tp.is_unit() {
+            return self.visit_str(&String::from("()"));
+        } else if tp.is_int() {
+            // widened to 64 bits, keeping the sign
+            let (native, wide) = if tp.is_unsigned() {
+                (rlan_uint_to_str as *mut c_void, Type::u64())
+            } else {
+                (rlan_int_to_str as *mut c_void, Type::int())
+            };
+            let args = [
+                self.main.i_convert(val, wide),
+                Value::constant(&self.main, Type::int(), radix as i64),
+                Value::constant(&self.main, Type::bool(), upper as i64),
+            ];
+            return self.main.i_native_call(native, &args, Type::str());
+        } else if tp.is_bigint() {
+            rlan_bigint_to_str as *mut c_void
+        } else if tp.is_char() {
+            rlan_char_to_str as *mut c_void
+        } else if tp.is_bool() {
+            rlan_bool_to_str as *mut c_void
+        } else {
+            panic!("{} can't be converted to a str", self.type_name(&tp));
+        };
+        self.main.i_native_call(native, &[*val], Type::str())
+    }
+
+    // operations on vecs and maps, whose signatures depend on the element types
+    fn collection_op(&mut self, name: &String, args: &Vec<Value>) -> Value {
+        let coll = match args.first() {
+            Some(coll) => coll.get_type(),
+            None => panic!("Invalid arguments for {}", name)
+        };
+        let call = |f: &Function, native: *mut c_void, args: &[Value], ret: Type| f.i_native_call(native, args, ret);
+        match (name.as_str(), args.len()) {
+            ("len", 1) if coll.is_str() => call(&self.main, rlan_str_len as *mut c_void, args, Type::int()),
+            ("len", 1) if coll.is_vec() => call(&self.main, rlan_vec_len as *mut c_void, args, Type::int()),
+            ("len", 1) if coll.is_map() => call(&self.main, rlan_map_len as *mut c_void, args, Type::int()),
+            ("get", 2) if coll.is_vec() => {
+                let i = self.coerce(args[1], Type::int(), "the index");
+                call(&self.main, rlan_vec_get as *mut c_void, &[args[0], i], coll.elem())
+            },
+            ("set", 3) if coll.is_vec() => {
+                let i = self.coerce(args[1], Type::int(), "the index");
+                let item = self.word_of(args[2], coll.elem());
+                call(&self.main, rlan_vec_set as *mut c_void, &[args[0], i, item], Type::void());
+                Value::constant_unit(&self.main)
+            },
+            ("push", 2) if coll.is_vec() => {
+                let item = self.word_of(args[1], coll.elem());
+                call(&self.main, rlan_vec_push as *mut c_void, &[args[0], item], Type::void());
+                Value::constant_unit(&self.main)
+            },
+            ("pop", 1) if coll.is_vec() => call(&self.main, rlan_vec_pop as *mut c_void, args, coll.elem()),
+            ("get", 2) if coll.is_map() => {
+                let key = self.word_of(args[1], coll.key());
+                call(&self.main, rlan_map_get as *mut c_void, &[args[0], key], coll.value())
+            },
+            ("insert", 3) if coll.is_map() => {
+                let key = self.word_of(args[1], coll.key());
+                let value = self.word_of(args[2], coll.value());
+                call(&self.main, rlan_map_insert as *mut c_void, &[args[0], key, value], Type::void());
+                Value::constant_unit(&self.main)
+            },
+            ("remove", 2) if coll.is_map() => {
+                let key = self.word_of(args[1], coll.key());
+                call(&self.main, rlan_map_remove as *mut c_void, &[args[0], key], Type::void());
+                Value::constant_unit(&self.main)
+            },
+            ("contains", 2) if coll.is_map() => {
+                let key = self.word_of(args[1], coll.key());
+                call(&self.main, rlan_map_contains as *mut c_void, &[args[0], key], Type::bool())
+            },
+            _ => panic!("Invalid arguments for {}", name)
+        }
+    }
+
+    // a value of the type, untyped int constants are converted
+    fn coerce(&mut self, val: Value, tp: Type, what: &str) -> Value {
+        let from = val.get_type();
+        if from.same_as(&tp) {
+            val
+        } else if from.is_int() && tp.is_int() && val.is_constant() {
+            self.convert(&val, tp)
+        } else {
+            panic!("Expected {} for {}, found {}", self.type_name(&tp), what, self.type_name(&from));
+        }
+    }
+
+    // collections store everything as 64-bit words, smaller values are widened
+    fn word_of(&mut self, val: Value, tp: Type) -> Value {
+        let val = self.coerce(val, tp, "the element");
+        if tp.is_tuple() || tp.is_void() {
+            panic!("Collections can't hold {}", self.type_name(&tp));
+        }
+        if tp.size() < 8 {
+            self.main.i_convert(&val, Type::int())
+        } else {
+            val
+        }
+    }
+
+    // the element type is the one of the first element, unless given
+    fn visit_vec(&mut self, elems: &Vec<Node>, tp: Option<Type>) -> Value {
+        let vals: Vec<Value> = elems.iter().map(|e| self.visit(e)).collect();
+        let tp = match (tp, vals.first()) {
+            (Some(tp), _) => tp,
+            (None, Some(first)) => Type::vec(first.get_type()),
+            (None, None) => panic!("The type of an empty vec must be given with `as`")
+        };
+        let vec = self.main.i_native_call(rlan_vec_new as *mut c_void, &[], tp);
+        for val in vals {
+            let item = self.word_of(val, tp.elem());
+            self.main.i_native_call(rlan_vec_push as *mut c_void, &[vec, item], Type::void());
+        }
+        vec
+    }
+
+    fn visit_map(&mut self, entries: &Vec<(Node, Node)>, tp: Option<Type>) -> Value {
+        let vals: Vec<(Value, Value)> = entries.iter().map(|(k, v)| (self.visit(k), self.visit(v))).collect();
+        let tp = match (tp, vals.first()) {
+            (Some(tp), _) => tp,
+            (None, Some((k, v))) => Type::map(k.get_type(), v.get_type()),
+            (None, None) => panic!("The type of an empty map must be given with `as`")
+        };
+        let key = tp.key();
+        if !(key.is_int() || key.is_bool() || key.is_char() || key.is_str()) {
+            panic!("{} can't be a map key", self.type_name(&key));
+        }
+        let str_keys = Value::constant(&self.main, Type::bool(), key.is_str() as i64);
+        let map = self.main.i_native_call(rlan_map_new as *mut c_void, &[str_keys], tp);
+        for (k, v) in vals {
+            let k = self.word_of(k, key);
+            let v = self.word_of(v, tp.value());
+            self.main.i_native_call(rlan_map_insert as *mut c_void, &[map, k, v], Type::void());
+        }
+        map
     }
 
     fn visit_vardef(&mut self, name: &String, val: &Box<Node>) -> Value {
-        let val = self.visit(&*val);
+        let val = self.visit(val);
         self.vtable.insert(name.to_string(), val);
-        Value::constant_void(&self.main) // TODO
+        Value::constant_unit(&self.main)
+    }
+
+    fn visit_const(&mut self, name: &String, val: &Box<Node>) -> Value {
+        match optimize::eval_const(val, &self.consts) {
+            Some(folded) => self.consts.insert(name.clone(), folded),
+            None => panic!("The value of {} isn't a constant expression", name)
+        };
+        Value::constant_unit(&self.main)
+    }
+
+    // globals live in memory from the runtime, so every function reads and writes the same value
+    fn visit_global(&mut self, name: &String, val: &Box<Node>) -> Value {
+        let val = self.visit(val);
+        let tp = val.get_type();
+        if tp.is_void() {
+            panic!("Global {} can't be void", name);
+        }
+        let addr = runtime::alloc_global(tp.size());
+        self.globals.insert(name.clone(), (addr, tp));
+        self.main.i_store_relative(&Value::constant_ptr(&self.main, addr), 0, &val);
+        Value::constant_unit(&self.main)
+    }
+
+    fn visit_assign(&mut self, name: &String, val: &Box<Node>) -> Value {
+        let val = self.visit(val);
+        let (addr, tp) = match self.globals.get(name) {
+            Some(global) if !self.vtable.contains_key(name) => *global,
+            _ => panic!("Only globals can be assigned, {} isn't one", name)
+        };
+        let val = if tp.is_int() && val.get_type().is_int() && val.is_constant() { self.convert(&val, tp) } else { val };
+        if !tp.same_as(&val.get_type()) {
+            panic!("Invalid type of the value assigned to {}", name);
+        }
+        self.main.i_store_relative(&Value::constant_ptr(&self.main, addr), 0, &val);
+        Value::constant_unit(&self.main)
+    }
+
+    // the elements are stored into a struct, which is passed around by value
+    fn visit_tuple(&mut self, elems: &Vec<Node>) -> Value {
+        let vals: Vec<Value> = elems.iter().map(|e| self.visit(e)).collect();
+        let types: Vec<Type> = vals.iter().map(|v| v.get_type()).collect();
+        if types.iter().any(|t| t.is_void()) {
+            panic!("Tuple elements can't be void");
+        }
+        let tp = Type::tuple(&types);
+        let tuple = Value::create(&self.main, &tp);
+        let addr = self.main.i_address_of(&tuple);
+        for (i, val) in vals.iter().enumerate() {
+            self.main.i_store_relative(&addr, tp.field_offset(i), val);
+        }
+        tuple
+    }
+
+    fn visit_destructure(&mut self, names: &Vec<String>, val: &Box<Node>) -> Value {
+        let tuple = self.visit(val);
+        let tp = tuple.get_type();
+        if !tp.is_tuple() || tp.field_count() != names.len() {
+            panic!("Expected a tuple of {} elements", names.len());
+        }
+        let addr = self.main.i_address_of(&tuple);
+        for (i, name) in names.iter().enumerate() {
+            let elem = self.main.i_load_relative(&addr, tp.field_offset(i), tp.field(i));
+            self.vtable.insert(name.clone(), elem);
+        }
+        Value::constant_unit(&self.main)
+    }
+
+    fn visit_raise(&mut self, val: &Box<Node>, span: &Span) -> Value {
+        let code = self.visit(val);
+        if !code.get_type().is_int() {
+            panic!("Raised value must be an int");
+        }
+        self.main.i_mark_offset(span.lo);
+        let code = self.main.i_convert(&code, Type::int());
+        let exception = self.main.i_native_call(rlan_exception_new as *mut c_void, &[code], Type::void_ptr());
+        self.main.i_throw(&exception);
+        Value::constant_unit(&self.main)
+    }
+
+    fn visit_try(&mut self, body: &Vec<Node>, name: &String, handler: &Vec<Node>) -> Value {
+        let (id, parent, handler_var, exception) = {
+            let catcher = self.catcher.as_mut().expect("Try outside of a catcher");
+            let id = catcher.tries.len() as i64;
+            let parent = catcher.current;
+            catcher.tries.push(Label::new());
+            catcher.current = id;
+            (id, parent, catcher.handler, catcher.exception)
+        };
+        self.main.i_store(&Value::constant_long(&self.main, id), &handler_var);
+        for n in body {
+            self.visit(n);
+        }
+        self.main.i_store(&Value::constant_long(&self.main, parent), &handler_var);
+        let end = Label::new();
+        self.main.i_branch(&end);
+        // the catch block, reached from the dispatcher in `finish_catcher`
+        {
+            let catcher = self.catcher.as_mut().unwrap();
+            catcher.current = parent;
+            catcher.tries[id as usize].place(&self.main);
+        }
+        self.main.i_store(&Value::constant_long(&self.main, parent), &handler_var);
+        let code = self.main.i_native_call(rlan_exception_catch as *mut c_void, &[exception], Type::int());
+        let shadowed = self.vtable.insert(name.clone(), code);
+        for n in handler {
+            self.visit(n);
+        }
+        match shadowed {
+            Some(val) => self.vtable.insert(name.clone(), val),
+            None => self.vtable.remove(name)
+        };
+        end.place(&self.main);
+        Value::constant_unit(&self.main)
+    }
+
+    // loop over the chars of a str, the bytes of a `bytes s`, the elements of a vec or the keys of a map.
+    // `pos` is a byte offset for strs and an index for vecs
+    fn visit_for(&mut self, name: &String, iter: &Box<Node>, body: &Vec<Node>) -> Value {
+        let mut seq = self.visit(iter);
+        // maps are iterated over their keys
+        if seq.get_type().is_map() {
+            let keys = Type::vec(seq.get_type().key());
+            seq = self.main.i_native_call(rlan_map_keys as *mut c_void, &[seq], keys);
+        }
+        // strs are indexed by byte and step over each char, bytes and vecs by element
+        let is_str = seq.get_type().is_str();
+        let (len_of, elem_at, elem) = if is_str {
+            (rlan_str_len as *mut c_void, rlan_str_char_at as *mut c_void, Type::char())
+        } else if seq.get_type().is_bytes() {
+            (rlan_str_len as *mut c_void, rlan_str_byte_at as *mut c_void, Type::u8())
+        } else if seq.get_type().is_vec() {
+            (rlan_vec_len as *mut c_void, rlan_vec_get as *mut c_void, seq.get_type().elem())
+        } else {
+            panic!("Only str, bytes and vecs can be iterated over");
+        };
+        let len = self.main.i_native_call(len_of, &[seq], Type::int());
+        let pos = Value::create(&self.main, &Type::int());
+        self.main.i_store(&Value::constant_long(&self.main, 0), &pos);
+        let item = Value::create(&self.main, &elem);
+        let (start, end) = (Label::new(), Label::new());
+        start.place(&self.main);
+        self.main.i_branch_if_not(&self.main.i_lt(&pos, &len), &end);
+        self.main.i_store(&self.main.i_native_call(elem_at, &[seq, pos], elem), &item);
+        let width = if is_str {
+            self.main.i_native_call(rlan_char_width as *mut c_void, &[item], Type::int())
+        } else {
+            Value::constant_long(&self.main, 1)
+        };
+        self.main.i_store(&self.main.i_add(&pos, &width), &pos);
+        let shadowed = self.vtable.insert(name.clone(), item);
+        for n in body {
+            self.visit(n);
+        }
+        match shadowed {
+            Some(val) => self.vtable.insert(name.clone(), val),
+            None => self.vtable.remove(name)
+        };
+        self.main.i_branch(&start);
+        end.place(&self.main);
+        Value::constant_unit(&self.main)
     }
 
     fn visit_if(&mut self, cond: &Box<Node>, then: &Box<Node>, other: &Box<Node>) -> Value {
@@ -190,7 +1278,177 @@ impl Builder {
             self.visit(then);
             elsetree.place(&self.main);
             self.visit(other);
-            Value::constant_void(&self.main)
+            Value::constant_unit(&self.main)
         }
     }
-}
\ No newline at end of file
+}
+// every child of the node except the bodies of definitions, which are other functions
+fn has_try(n: &Node) -> bool {
+    match n {
+        Node::Try(..) => true,
+        Node::If(cond, then, other) => has_try(cond) || has_try(then) || has_try(other),
+        Node::For(_, seq, body) => has_try(seq) || body.iter().any(has_try),
+        Node::BinOp(lhs, _, rhs, _) => has_try(lhs) || has_try(rhs),
+        Node::Call(ns, _) | Node::Tuple(ns) | Node::VecLit(ns) => ns.iter().any(has_try),
+        Node::MethodCall(recv, _, args, _) => has_try(recv) || args.iter().any(has_try),
+        Node::MapLit(entries) => entries.iter().any(|(k, v)| has_try(k) || has_try(v)),
+        Node::Format(parts, _) => parts.iter().any(|part| match part {
+            FmtPart::Arg(arg, _) => has_try(arg),
+            _ => false
+        }),
+        Node::VarDef(_, val) | Node::Destructure(_, val) | Node::Const(_, val) | Node::Global(_, val) |
+        Node::Assign(_, val) | Node::Ret(val) | Node::Raise(val, _) | Node::Cast(val, _, _) => has_try(val),
+        _ => false
+    }
+}
+
+// bind the type parameters appearing in `argtype` by matching it against the type of an argument
+fn infer(params: &[String], argtype: &TypeExpr, tp: Type, bindings: &mut HashMap<String, Type>) {
+    match argtype {
+        TypeExpr::Named(name) if params.contains(name) => match bindings.get(name) {
+            Some(bound) if !bound.same_as(&tp) => panic!("Conflicting types for the type parameter {}", name),
+            Some(_) => {},
+            None => {
+                bindings.insert(name.clone(), tp);
+            }
+        },
+        TypeExpr::Tuple(elems) if tp.is_tuple() && tp.field_count() == elems.len() => {
+            for (i, elem) in elems.iter().enumerate() {
+                infer(params, elem, tp.field(i), bindings);
+            }
+        },
+        TypeExpr::Generic(name, args) => match (name.as_str(), args.as_slice()) {
+            ("Vec", [elem]) if tp.is_vec() => infer(params, elem, tp.elem(), bindings),
+            ("Map", [key, value]) if tp.is_map() => {
+                infer(params, key, tp.key(), bindings);
+                infer(params, value, tp.value(), bindings);
+            },
+            _ => {}
+        },
+        _ => {}
+    }
+}
+
+// called from JIT code when a function compiled at level 0 becomes hot
+extern "C" fn tier_up(tier: *mut c_void) {
+    let builder = ACTIVE.with(|a| a.get());
+    if builder.is_null() {
+        return;
+    }
+    unsafe {
+        let source = (*(tier as *const Tier)).source;
+        (*builder).tier_up(source);
+    }
+}
+
+// called by libjit on the first call of a lazily compiled function
+extern "C" fn compile_on_demand(func: *mut c_void) -> i32 {
+    let builder = ACTIVE.with(|a| a.get());
+    if builder.is_null() {
+        return RESULT_COMPILE_ERROR;
+    }
+    // codegen errors are panics, which must not unwind into libjit
+    let built = panic::catch_unwind(AssertUnwindSafe(|| unsafe {
+        (*builder).compile_on_demand(func)
+    }));
+    match built {
+        Ok(true) => RESULT_OK,
+        _ => RESULT_COMPILE_ERROR
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grammar::CodeParser;
+    use crate::lexer::Lexer;
+
+    fn parse(code: &str) -> Vec<Node> {
+        CodeParser::new().parse(&mut Vec::new(), &mut Vec::new(), Lexer::new(code)).unwrap()
+    }
+
+    // the exit status of a script
+    fn run(code: &str) -> i64 {
+        run_with(code, |_| {})
+    }
+
+    fn run_with<F: FnOnce(&mut Builder)>(code: &str, setup: F) -> i64 {
+        let mut builder = Builder::new();
+        setup(&mut builder);
+        builder.build_main(&parse(code));
+        builder.execute().ok().expect("Uncaught exception")
+    }
+
+    #[test]
+    fn try_in_let() {
+        let code = parse("let x = try { raise 1 } catch e { 2 }");
+        assert!(code.iter().any(has_try));
+    }
+
+    #[test]
+    fn try_in_expressions() {
+        for code in &["<- 1 + (try { 1 } catch e { 2 })", "f (try { 1 } catch e { 2 })",
+            "[1, try { 1 } catch e { 2 }]", "x.m(try { 1 } catch e { 2 })"] {
+            assert!(parse(code).iter().any(has_try), "{}", code);
+        }
+        assert!(!parse("def f -> int { try { 1 } catch e { 2 } }").iter().any(has_try));
+    }
+
+    #[test]
+    fn exit_status() {
+        let gcd = "def gcd x:int y:int -> int {
+            if x == y: <- x else if x < y: <- gcd x (y - x) else <- gcd (x - y) y
+        };";
+        assert_eq!(run(&format!("{}\ngcd 30 25", gcd)), 5);
+        // main returns a nint, not an i32
+        assert_eq!(run("4294967301"), 4294967301);
+    }
+
+    #[test]
+    fn late_builds_call_the_functions_of_the_definition() {
+        // f is built after g is redefined, on its first call or when it gets hot
+        let code = "def g -> int <- 1; def f -> int <- g (); def g -> int <- 2; f () + f () + g ()";
+        assert_eq!(run_with(code, |b| b.lazy = true), 4);
+        assert_eq!(run_with(code, |b| {
+            b.lazy = false;
+            b.tier_threshold = Some(1);
+        }), 4);
+    }
+
+    #[test]
+    fn deep_tail_calls() {
+        let code = "def count[T] n:int acc:T -> T { if n == 0: <- acc else <- count (n - 1) acc };
+            trait Down { def down self n:int -> int };
+            impl Down for int { def down self n:int -> int { if n == 0: <- self else <- self.down(n - 1) } };
+            count 1000000 7 + 5.down(1000000)";
+        assert_eq!(run(code), 12);
+        assert_eq!(run_with(code, |b| b.lazy = false), 12);
+    }
+
+    #[test]
+    #[should_panic(expected = "Method name of Named for int is already implemented by trait Show")]
+    fn same_method_in_two_traits() {
+        run("trait Show { def name self -> str };
+            trait Named { def name self -> str };
+            impl Show for int { def name self -> str <- \"show\" };
+            impl Named for int { def name self -> str <- \"named\" };
+            0");
+    }
+
+    #[test]
+    fn aliases_share_the_native() {
+        let builder = Builder::new();
+        for (alias, name) in stdlib::aliases() {
+            match (&builder.ftable[alias], &builder.ftable[name]) {
+                (Either::Left(a), Either::Left(n)) => assert_eq!(a.ptr, n.ptr),
+                _ => panic!("{} isn't a native", alias)
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Parameter u of f can't be of type unit")]
+    fn unit_parameters() {
+        run("def f u:unit -> int <- 1; f ()");
+    }
+}
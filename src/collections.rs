@@ -0,0 +1,118 @@
+use crate::runtime;
+use crate::wrapper::RESULT_OUT_OF_BOUNDS;
+use libc::c_void;
+use std::collections::HashMap;
+
+// elements are stored as 64-bit words whatever their type, JIT code
+// converts them to and from the element type of the vec
+pub type RVec = Vec<u64>;
+
+pub fn new_vec(items: RVec) -> *mut c_void {
+    runtime::alloc(items)
+}
+
+pub unsafe fn get_vec<'a>(v: *mut c_void) -> &'a mut RVec {
+    &mut *(v as *mut RVec)
+}
+
+pub extern "C" fn rlan_vec_new() -> *mut c_void {
+    new_vec(Vec::new())
+}
+
+pub extern "C" fn rlan_vec_len(v: *mut c_void) -> i64 {
+    unsafe { get_vec(v) }.len() as i64
+}
+
+pub extern "C" fn rlan_vec_get(v: *mut c_void, i: i64) -> u64 {
+    let v = unsafe { get_vec(v) };
+    if i < 0 || i as usize >= v.len() {
+        runtime::raise(RESULT_OUT_OF_BOUNDS as i64, "index out of bounds");
+    }
+    v[i as usize]
+}
+
+pub extern "C" fn rlan_vec_set(v: *mut c_void, i: i64, item: u64) {
+    let v = unsafe { get_vec(v) };
+    if i < 0 || i as usize >= v.len() {
+        runtime::raise(RESULT_OUT_OF_BOUNDS as i64, "index out of bounds");
+    }
+    v[i as usize] = item;
+}
+
+pub extern "C" fn rlan_vec_push(v: *mut c_void, item: u64) {
+    unsafe { get_vec(v) }.push(item);
+}
+
+pub extern "C" fn rlan_vec_pop(v: *mut c_void) -> u64 {
+    match unsafe { get_vec(v) }.pop() {
+        Some(item) => item,
+        None => runtime::raise(RESULT_OUT_OF_BOUNDS as i64, "pop from an empty vec")
+    }
+}
+
+// strs are compared by their text, everything else by the word
+#[derive(PartialEq, Eq, Hash)]
+enum Key {
+    Word(u64),
+    Str(String),
+}
+
+// the words of the key and of the value by the key
+pub struct RMap {
+    str_keys: bool,
+    entries: HashMap<Key, (u64, u64)>,
+}
+
+impl RMap {
+    fn key(&self, word: u64) -> Key {
+        if self.str_keys {
+            Key::Str(unsafe { runtime::get_str(word as *mut c_void) }.clone())
+        } else {
+            Key::Word(word)
+        }
+    }
+}
+
+unsafe fn get_map<'a>(m: *mut c_void) -> &'a mut RMap {
+    &mut *(m as *mut RMap)
+}
+
+pub extern "C" fn rlan_map_new(str_keys: bool) -> *mut c_void {
+    runtime::alloc(RMap {str_keys, entries: HashMap::new()})
+}
+
+pub extern "C" fn rlan_map_len(m: *mut c_void) -> i64 {
+    unsafe { get_map(m) }.entries.len() as i64
+}
+
+pub extern "C" fn rlan_map_insert(m: *mut c_void, key: u64, value: u64) {
+    let m = unsafe { get_map(m) };
+    let k = m.key(key);
+    m.entries.insert(k, (key, value));
+}
+
+pub extern "C" fn rlan_map_get(m: *mut c_void, key: u64) -> u64 {
+    let m = unsafe { get_map(m) };
+    let found = m.entries.get(&m.key(key)).map(|(_, value)| *value);
+    match found {
+        Some(value) => value,
+        None => runtime::raise(RESULT_OUT_OF_BOUNDS as i64, "key not found")
+    }
+}
+
+pub extern "C" fn rlan_map_contains(m: *mut c_void, key: u64) -> bool {
+    let m = unsafe { get_map(m) };
+    m.entries.contains_key(&m.key(key))
+}
+
+// removing a missing key does nothing
+pub extern "C" fn rlan_map_remove(m: *mut c_void, key: u64) {
+    let m = unsafe { get_map(m) };
+    let k = m.key(key);
+    m.entries.remove(&k);
+}
+
+// a vec of the keys, in no particular order
+pub extern "C" fn rlan_map_keys(m: *mut c_void) -> *mut c_void {
+    new_vec(unsafe { get_map(m) }.entries.values().map(|(key, _)| *key).collect())
+}
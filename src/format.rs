@@ -0,0 +1,251 @@
+use crate::lexer::SyntaxError;
+use crate::myast::{Node, FmtPart, FmtSpec, Align};
+use crate::runtime;
+use crate::stdlib::to_char;
+use libc::c_void;
+use std::fmt;
+use std::mem;
+
+// splits the text of a str literal into literal parts and `{...}` placeholders,
+// `{{` and `}}` are escaped braces
+pub fn parse(s: &str) -> Result<Vec<FmtPart>, &'static str> {
+    let mut parts = Vec::new();
+    let mut lit = String::new();
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                lit.push('{');
+            },
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                lit.push('}');
+            },
+            '}' => return Err("unmatched `}` in a str, use `}}` for a brace"),
+            '{' => {
+                let mut inner = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => inner.push(c),
+                        None => return Err("unterminated `{` in a str, use `{{` for a brace")
+                    }
+                }
+                if !lit.is_empty() {
+                    parts.push(FmtPart::Lit(mem::take(&mut lit)));
+                }
+                parts.push(placeholder(&inner)?);
+            },
+            c => lit.push(c)
+        }
+    }
+    if !lit.is_empty() {
+        parts.push(FmtPart::Lit(lit));
+    }
+    Ok(parts)
+}
+
+// `name:spec`, the name is a variable, like Rust's inline arguments, or the position
+// of an argument of `format`, which is the next one when it's empty
+fn placeholder(s: &str) -> Result<FmtPart, &'static str> {
+    let (name, spec) = match s.find(':') {
+        Some(i) => (s[..i].trim(), parse_spec(&s[i + 1..])?),
+        None => (s.trim(), FmtSpec::default())
+    };
+    if name.is_empty() {
+        Ok(FmtPart::Pos(None, spec))
+    } else if let Ok(i) = name.parse::<usize>() {
+        Ok(FmtPart::Pos(Some(i), spec))
+    } else if is_ident(name) {
+        Ok(FmtPart::Arg(Box::new(Node::Ident(String::from(name))), spec))
+    } else {
+        Err("only variables can be interpolated in a str")
+    }
+}
+
+// `{}` and `{0}` are only filled by the arguments of `format`, in any other str they
+// are an error at the span of the literal
+pub fn check_positions(n: &Node, errors: &mut Vec<SyntaxError>) {
+    let check = |ns: &[Node], errors: &mut Vec<SyntaxError>| for n in ns {
+        check_positions(n, errors);
+    };
+    match n {
+        Node::Format(parts, span) if parts.iter().any(|part| matches!(part, FmtPart::Pos(..))) => {
+            errors.push(SyntaxError::new("`{}` is only filled by the arguments of format, use `{{` for a brace", *span));
+        },
+        Node::Call(ns, _) => match ns.as_slice() {
+            [Node::Ident(f), Node::Format(..), args @ ..] if f == "format" => check(args, errors),
+            _ => check(ns, errors)
+        },
+        Node::If(cond, then, other) => {
+            check_positions(cond, errors);
+            check_positions(then, errors);
+            check_positions(other, errors);
+        },
+        Node::BinOp(lhs, _, rhs, _) => {
+            check_positions(lhs, errors);
+            check_positions(rhs, errors);
+        },
+        Node::MethodCall(recv, _, args, _) => {
+            check_positions(recv, errors);
+            check(args, errors);
+        },
+        Node::MapLit(entries) => for (k, v) in entries {
+            check_positions(k, errors);
+            check_positions(v, errors);
+        },
+        Node::For(_, val, ns) => {
+            check_positions(val, errors);
+            check(ns, errors);
+        },
+        Node::Try(body, _, handler) => {
+            check(body, errors);
+            check(handler, errors);
+        },
+        Node::Tuple(ns) | Node::VecLit(ns) | Node::FuncDef(_, _, _, ns) | Node::GenericDef(_, _, _, _, ns) |
+        Node::Impl(_, _, ns) => check(ns, errors),
+        Node::VarDef(_, val) | Node::Destructure(_, val) | Node::Const(_, val) | Node::Global(_, val) |
+        Node::Assign(_, val) | Node::Ret(val) | Node::Raise(val, _) | Node::Cast(val, _, _) => check_positions(val, errors),
+        _ => {}
+    }
+}
+
+fn is_ident(s: &str) -> bool {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => chars.all(|c| c.is_ascii_alphanumeric() || c == '_'),
+        _ => false
+    }
+}
+
+fn parse_spec(s: &str) -> Result<FmtSpec, &'static str> {
+    let mut spec = FmtSpec::default();
+    let chars: Vec<char> = s.chars().collect();
+    let align = |c: char| match c {
+        '<' => Some(Align::Left),
+        '>' => Some(Align::Right),
+        '^' => Some(Align::Center),
+        _ => None
+    };
+    let mut i = 0;
+    if chars.len() >= 2 && align(chars[1]).is_some() {
+        spec.fill = chars[0];
+        spec.align = align(chars[1]);
+        i = 2;
+    } else if !chars.is_empty() && align(chars[0]).is_some() {
+        spec.align = align(chars[0]);
+        i = 1;
+    }
+    if chars.get(i) == Some(&'0') {
+        spec.zero = true;
+        i += 1;
+    }
+    let (width, next) = number(&chars, i);
+    spec.width = width.unwrap_or(0);
+    i = next;
+    if chars.get(i) == Some(&'.') {
+        let (precision, next) = number(&chars, i + 1);
+        if precision.is_none() {
+            return Err("missing precision after `.` in a format spec");
+        }
+        spec.precision = precision;
+        i = next;
+    }
+    match &chars[i..] {
+        [] => {},
+        ['x'] => spec.radix = 16,
+        ['X'] => {
+            spec.radix = 16;
+            spec.upper = true;
+        },
+        ['o'] => spec.radix = 8,
+        ['b'] => spec.radix = 2,
+        _ => return Err("invalid format spec")
+    }
+    Ok(spec)
+}
+
+// the decimal number starting at `start` and the index after it
+fn number(chars: &[char], start: usize) -> (Option<usize>, usize) {
+    let end = chars[start..].iter().position(|c| !c.is_ascii_digit()).map_or(chars.len(), |n| start + n);
+    (chars[start..end].iter().collect::<String>().parse().ok(), end)
+}
+
+fn int_to_string<T>(n: T, radix: i64, upper: bool) -> String
+    where T: fmt::Display + fmt::LowerHex + fmt::UpperHex + fmt::Octal + fmt::Binary {
+    match radix {
+        16 if upper => format!("{:X}", n),
+        16 => format!("{:x}", n),
+        8 => format!("{:o}", n),
+        2 => format!("{:b}", n),
+        _ => n.to_string()
+    }
+}
+
+// the signed ints are widened to i64 and the unsigned ones to u64 before the call
+pub extern "C" fn rlan_int_to_str(n: i64, radix: i64, upper: bool) -> *mut c_void {
+    runtime::new_str(int_to_string(n, radix, upper))
+}
+
+pub extern "C" fn rlan_uint_to_str(n: u64, radix: i64, upper: bool) -> *mut c_void {
+    runtime::new_str(int_to_string(n, radix, upper))
+}
+
+pub extern "C" fn rlan_char_to_str(c: u32) -> *mut c_void {
+    runtime::new_str(to_char(c).to_string())
+}
+
+pub extern "C" fn rlan_bool_to_str(b: bool) -> *mut c_void {
+    runtime::new_str(b.to_string())
+}
+
+// applies the width and the precision of a spec, a negative precision is none,
+// zero padding goes after the sign of a number
+pub extern "C" fn rlan_str_pad(s: *mut c_void, fill: u32, align: i64, width: i64, precision: i64, zero: bool) -> *mut c_void {
+    let s = unsafe { runtime::get_str(s) };
+    let mut s: String = if precision >= 0 { s.chars().take(precision as usize).collect() } else { s.clone() };
+    let pad = (width as usize).saturating_sub(s.chars().count());
+    if pad == 0 {
+        return runtime::new_str(s);
+    }
+    if zero {
+        let sign = if s.starts_with('-') { s.remove(0); "-" } else { "" };
+        return runtime::new_str(format!("{}{}{}", sign, "0".repeat(pad), s));
+    }
+    let fill = to_char(fill).to_string();
+    let (before, after) = match align {
+        0 => (0, pad),
+        1 => (pad, 0),
+        _ => (pad / 2, pad - pad / 2)
+    };
+    runtime::new_str(format!("{}{}{}", fill.repeat(before), s, fill.repeat(after)))
+}
+
+pub extern "C" fn rlan_str_concat(a: *mut c_void, b: *mut c_void) -> *mut c_void {
+    let (a, b) = unsafe { (runtime::get_str(a), runtime::get_str(b)) };
+    runtime::new_str(format!("{}{}", a, b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grammar::CodeParser;
+    use crate::lexer::Lexer;
+
+    fn position_errors(code: &str) -> Vec<(usize, usize)> {
+        let mut errors = Vec::new();
+        for n in &CodeParser::new().parse(&mut Vec::new(), &mut Vec::new(), Lexer::new(code)).unwrap() {
+            check_positions(n, &mut errors);
+        }
+        errors.iter().map(|err| (err.span.lo, err.span.hi)).collect()
+    }
+
+    #[test]
+    fn positions_only_in_format() {
+        assert!(position_errors("let s = format \"{} and {0}\" 1;").is_empty());
+        assert!(position_errors("let x = 1; print \"x is {x:>4}\";").is_empty());
+        assert_eq!(position_errors("print \"{}\";"), vec![(6, 10)]);
+        assert_eq!(position_errors("def f -> str { <- format \"{}\" \"{0}\" }"), vec![(30, 35)]);
+    }
+}
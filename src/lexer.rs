@@ -0,0 +1,479 @@
+use crate::literal;
+use crate::myast::{Node, Span, Trivia};
+use lalrpop_util::ParseError;
+use std::fmt;
+
+#[derive(Clone, Debug)]
+pub enum TokenKind {
+    Id(String),
+    Num(Node), // the value of the literal, a Number, BigNumber or TypedNumber
+    Str(String), // with the escapes replaced
+    RawStr(String),
+    Char(char),
+    // keywords
+    Def, Const, Global, Trait, Impl, For, In, If, Else, Let, Try, Catch, Raise, And, Or, As, True, False, SelfValue,
+    // punctuation
+    LParen, RParen, LBrace, RBrace, LBracket, RBracket, Comma, Semi, Colon, Dot,
+    Arrow, Ret, Pipe, Assign, Eq, Neq, Lt, Le, Gt, Ge, Plus, Minus, Star, Slash,
+}
+
+#[derive(Clone, Debug)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug)]
+pub struct SyntaxError {
+    pub msg: String,
+    pub span: Span,
+}
+
+impl SyntaxError {
+    pub fn new<S: Into<String>>(msg: S, span: Span) -> Self {
+        SyntaxError {msg: msg.into(), span}
+    }
+}
+
+fn keyword(s: &str) -> Option<TokenKind> {
+    Some(match s {
+        "def" => TokenKind::Def,
+        "const" => TokenKind::Const,
+        "global" => TokenKind::Global,
+        "trait" => TokenKind::Trait,
+        "impl" => TokenKind::Impl,
+        "for" => TokenKind::For,
+        "in" => TokenKind::In,
+        "if" => TokenKind::If,
+        "else" => TokenKind::Else,
+        "let" => TokenKind::Let,
+        "try" => TokenKind::Try,
+        "catch" => TokenKind::Catch,
+        "raise" => TokenKind::Raise,
+        "and" => TokenKind::And,
+        "or" => TokenKind::Or,
+        "as" => TokenKind::As,
+        "true" => TokenKind::True,
+        "false" => TokenKind::False,
+        "self" => TokenKind::SelfValue,
+        _ => return None
+    })
+}
+
+impl TokenKind {
+    // the source text of keywords and punctuation
+    pub fn text(&self) -> &'static str {
+        match self {
+            TokenKind::Id(_) | TokenKind::Num(_) | TokenKind::Str(_) | TokenKind::RawStr(_) | TokenKind::Char(_) => "",
+            TokenKind::Def => "def",
+            TokenKind::Const => "const",
+            TokenKind::Global => "global",
+            TokenKind::Trait => "trait",
+            TokenKind::Impl => "impl",
+            TokenKind::For => "for",
+            TokenKind::In => "in",
+            TokenKind::If => "if",
+            TokenKind::Else => "else",
+            TokenKind::Let => "let",
+            TokenKind::Try => "try",
+            TokenKind::Catch => "catch",
+            TokenKind::Raise => "raise",
+            TokenKind::And => "and",
+            TokenKind::Or => "or",
+            TokenKind::As => "as",
+            TokenKind::True => "true",
+            TokenKind::False => "false",
+            TokenKind::SelfValue => "self",
+            TokenKind::LParen => "(",
+            TokenKind::RParen => ")",
+            TokenKind::LBrace => "{",
+            TokenKind::RBrace => "}",
+            TokenKind::LBracket => "[",
+            TokenKind::RBracket => "]",
+            TokenKind::Comma => ",",
+            TokenKind::Semi => ";",
+            TokenKind::Colon => ":",
+            TokenKind::Dot => ".",
+            TokenKind::Arrow => "->",
+            TokenKind::Ret => "<-",
+            TokenKind::Pipe => "|>",
+            TokenKind::Assign => "=",
+            TokenKind::Eq => "==",
+            TokenKind::Neq => "!=",
+            TokenKind::Lt => "<",
+            TokenKind::Le => "<=",
+            TokenKind::Gt => ">",
+            TokenKind::Ge => ">=",
+            TokenKind::Plus => "+",
+            TokenKind::Minus => "-",
+            TokenKind::Star => "*",
+            TokenKind::Slash => "/",
+        }
+    }
+}
+
+impl fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TokenKind::Id(name) => write!(f, "identifier `{}`", name),
+            TokenKind::Num(_) => write!(f, "number"),
+            TokenKind::Str(_) | TokenKind::RawStr(_) => write!(f, "str"),
+            TokenKind::Char(_) => write!(f, "char"),
+            kind => write!(f, "`{}`", kind.text())
+        }
+    }
+}
+
+// splits a script into tokens, skipping whitespace, `#` comments and nested `/* */` comments,
+// errors are collected in `errors` and a literal with an error still gives a token
+pub struct Lexer<'a> {
+    text: &'a str,
+    pos: usize,
+    pub errors: Vec<SyntaxError>,
+    pub trivia: Vec<(usize, Trivia)>, // the skipped comments and blank lines, for `rlan fmt`
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(text: &'a str) -> Self {
+        Lexer {text, pos: 0, errors: Vec::new(), trivia: Vec::new()}
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.text[self.pos..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn bump_while<F: Fn(char) -> bool>(&mut self, pred: F) {
+        while self.peek().is_some_and(&pred) {
+            self.bump();
+        }
+    }
+
+    fn error<S: Into<String>>(&mut self, msg: S, lo: usize, hi: usize) {
+        self.errors.push(SyntaxError::new(msg, Span::new(lo, hi)));
+    }
+
+    pub fn next_token(&mut self) -> Option<Token> {
+        loop {
+            self.skip_trivia();
+            let lo = self.pos;
+            let kind = match self.bump()? {
+                'r' if self.rest().trim_start_matches('#').starts_with('"') => self.raw_str(lo),
+                c if c.is_ascii_alphabetic() || c == '_' => {
+                    self.bump_while(|c| c.is_ascii_alphanumeric() || c == '_');
+                    let s = &self.text[lo..self.pos];
+                    keyword(s).unwrap_or_else(|| TokenKind::Id(String::from(s)))
+                },
+                c if c.is_ascii_digit() => self.number(lo),
+                '"' => self.str(lo),
+                '\'' => self.char(lo),
+                c => match self.punct(c) {
+                    Some(kind) => kind,
+                    None => {
+                        self.error(format!("invalid character `{}`", c), lo, self.pos);
+                        continue;
+                    }
+                }
+            };
+            return Some(Token {kind, span: Span::new(lo, self.pos)});
+        }
+    }
+
+    fn skip_trivia(&mut self) {
+        // newlines since the last token or comment, the second one ends a blank line
+        let mut newlines = 0;
+        loop {
+            let rest = self.rest();
+            let lo = self.pos;
+            if rest.starts_with('#') {
+                self.pos += rest.find('\n').unwrap_or(rest.len());
+                self.comment(lo);
+                newlines = 0;
+            } else if rest.starts_with("/*") {
+                self.block_comment();
+                self.comment(lo);
+                newlines = 0;
+            } else if let Some(c) = self.peek().filter(|c| c.is_whitespace()) {
+                self.bump();
+                if c == '\n' {
+                    newlines += 1;
+                    if newlines == 2 {
+                        self.trivia.push((lo, Trivia::Blank));
+                    }
+                }
+            } else {
+                return;
+            }
+        }
+    }
+
+    fn comment(&mut self, lo: usize) {
+        let line_start = self.text[..lo].rfind('\n').map_or(0, |i| i + 1);
+        let after_code = !self.text[line_start..lo].trim().is_empty();
+        let text = String::from(self.text[lo..self.pos].trim_end());
+        self.trivia.push((lo, Trivia::Comment(text, after_code)));
+    }
+
+    fn block_comment(&mut self) {
+        let lo = self.pos;
+        let mut depth = 0;
+        loop {
+            if self.rest().starts_with("/*") {
+                depth += 1;
+                self.pos += 2;
+            } else if self.rest().starts_with("*/") {
+                depth -= 1;
+                self.pos += 2;
+                if depth == 0 {
+                    return;
+                }
+            } else if self.bump().is_none() {
+                return self.error("unterminated comment, `/*` is never closed", lo, lo + 2);
+            }
+        }
+    }
+
+    // the letters after the digits are part of the literal, so `12ab` is an error instead of `12 ab`
+    fn number(&mut self, lo: usize) -> TokenKind {
+        self.bump_while(|c| c.is_ascii_alphanumeric() || c == '_');
+        match literal::int(&self.text[lo..self.pos]) {
+            Ok(n) => TokenKind::Num(n),
+            Err(msg) => {
+                self.error(msg, lo, self.pos);
+                TokenKind::Num(Node::Error)
+            }
+        }
+    }
+
+    fn str(&mut self, lo: usize) -> TokenKind {
+        loop {
+            match self.bump() {
+                Some('"') => break,
+                Some('\\') => {
+                    self.bump();
+                },
+                Some(_) => {},
+                None => {
+                    self.error("unterminated str, `\"` is never closed", lo, lo + 1);
+                    return TokenKind::Str(String::new());
+                }
+            }
+        }
+        TokenKind::Str(self.unescape(lo, 1))
+    }
+
+    // chars end on the same line
+    fn char(&mut self, lo: usize) -> TokenKind {
+        loop {
+            match self.bump() {
+                Some('\'') => break,
+                Some('\\') if self.peek() != Some('\n') => {
+                    self.bump();
+                },
+                Some('\n') | None => {
+                    self.error("unterminated char, `'` is never closed", lo, lo + 1);
+                    return TokenKind::Char('\0');
+                },
+                Some(_) => {}
+            }
+        }
+        let s = self.unescape(lo, 1);
+        let mut chars = s.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => return TokenKind::Char(c),
+            (None, _) => self.error("empty char", lo, self.pos),
+            _ => self.error("a char can't hold more than one character, strs are written with `\"`", lo, self.pos)
+        }
+        TokenKind::Char('\0')
+    }
+
+    // the text of the literal from `lo` to here, without the quotes
+    fn unescape(&mut self, lo: usize, quote: usize) -> String {
+        let start = lo + quote;
+        match literal::unescape(&self.text[start..self.pos - quote]) {
+            Ok(s) => s,
+            Err((msg, offset)) => {
+                // the span of the escape sequence
+                let escape = &self.text[start + offset..];
+                let len = escape.chars().take(2).map(char::len_utf8).sum::<usize>();
+                self.error(msg, start + offset, start + offset + len);
+                String::new()
+            }
+        }
+    }
+
+    // r"..." or r#"..."# with any number of `#`, which can contain a `"` followed by fewer `#`
+    fn raw_str(&mut self, lo: usize) -> TokenKind {
+        let hashes = self.rest().chars().take_while(|c| *c == '#').count();
+        self.pos += hashes + 1;
+        let close = format!("\"{}", "#".repeat(hashes));
+        match self.rest().find(&close) {
+            Some(end) => {
+                let s = String::from(&self.rest()[..end]);
+                self.pos += end + close.len();
+                TokenKind::RawStr(s)
+            },
+            None => {
+                self.error(format!("unterminated raw str, `{}` is never found", close), lo, self.pos);
+                self.pos = self.text.len();
+                TokenKind::RawStr(String::new())
+            }
+        }
+    }
+
+    fn punct(&mut self, c: char) -> Option<TokenKind> {
+        let (kind, two_chars) = match (c, self.peek()) {
+            ('-', Some('>')) => (TokenKind::Arrow, true),
+            ('<', Some('-')) => (TokenKind::Ret, true),
+            ('|', Some('>')) => (TokenKind::Pipe, true),
+            ('=', Some('=')) => (TokenKind::Eq, true),
+            ('!', Some('=')) => (TokenKind::Neq, true),
+            ('<', Some('=')) => (TokenKind::Le, true),
+            ('>', Some('=')) => (TokenKind::Ge, true),
+            ('(', _) => (TokenKind::LParen, false),
+            (')', _) => (TokenKind::RParen, false),
+            ('{', _) => (TokenKind::LBrace, false),
+            ('}', _) => (TokenKind::RBrace, false),
+            ('[', _) => (TokenKind::LBracket, false),
+            (']', _) => (TokenKind::RBracket, false),
+            (',', _) => (TokenKind::Comma, false),
+            (';', _) => (TokenKind::Semi, false),
+            (':', _) => (TokenKind::Colon, false),
+            ('.', _) => (TokenKind::Dot, false),
+            ('=', _) => (TokenKind::Assign, false),
+            ('<', _) => (TokenKind::Lt, false),
+            ('>', _) => (TokenKind::Gt, false),
+            ('+', _) => (TokenKind::Plus, false),
+            ('-', _) => (TokenKind::Minus, false),
+            ('*', _) => (TokenKind::Star, false),
+            ('/', _) => (TokenKind::Slash, false),
+            _ => return None
+        };
+        if two_chars {
+            self.bump();
+        }
+        Some(kind)
+    }
+}
+
+// the tokens in the form the parser takes them, errors are in `errors`
+impl<'a> Iterator for Lexer<'a> {
+    type Item = Result<(usize, TokenKind, usize), SyntaxError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_token().map(|Token {kind, span}| Ok((span.lo, kind, span.hi)))
+    }
+}
+
+impl From<ParseError<usize, TokenKind, SyntaxError>> for SyntaxError {
+    fn from(err: ParseError<usize, TokenKind, SyntaxError>) -> Self {
+        match err {
+            ParseError::User {error} => error,
+            ParseError::InvalidToken {location} => SyntaxError::new("invalid token", Span::new(location, location)),
+            ParseError::UnrecognizedEOF {location, expected} => {
+                SyntaxError::new(format!("unexpected end of file{}", expected_list(&expected)), Span::new(location, location))
+            },
+            ParseError::UnrecognizedToken {token: (lo, tok, hi), expected} => {
+                SyntaxError::new(format!("unexpected {}{}", tok, expected_list(&expected)), Span::new(lo, hi))
+            },
+            ParseError::ExtraToken {token: (lo, tok, hi)} => SyntaxError::new(format!("unexpected {}", tok), Span::new(lo, hi)),
+        }
+    }
+}
+
+// the parser names terminals by their quoted text in the grammar
+fn expected_list(expected: &[String]) -> String {
+    let mut names: Vec<String> = Vec::new();
+    for e in expected {
+        let name = match e.trim_matches('"') {
+            "Id" => String::from("identifier"),
+            "Num" => String::from("number"),
+            "Str" | "RawStr" => String::from("str"),
+            "Char" => String::from("char"),
+            text => format!("`{}`", text)
+        };
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    match names.as_slice() {
+        [] => String::new(),
+        [one] => format!(", expected {}", one),
+        _ => format!(", expected one of {}", names.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Spans = Vec<(String, usize, usize)>;
+
+    // the tokens and the errors as `(text, lo, hi)`, with the kinds in their Debug form
+    fn lex(code: &str) -> (Spans, Spans) {
+        let mut lexer = Lexer::new(code);
+        let mut tokens = Vec::new();
+        while let Some(Token {kind, span}) = lexer.next_token() {
+            tokens.push((format!("{:?}", kind), span.lo, span.hi));
+        }
+        let errors = lexer.errors.iter().map(|err| (err.msg.clone(), err.span.lo, err.span.hi)).collect();
+        (tokens, errors)
+    }
+
+    fn tok(kind: &str, lo: usize, hi: usize) -> (String, usize, usize) {
+        (String::from(kind), lo, hi)
+    }
+
+    #[test]
+    fn minus_without_spaces() {
+        let (tokens, errors) = lex("x-1");
+        assert_eq!(tokens, vec![tok("Id(\"x\")", 0, 1), tok("Minus", 1, 2), tok("Num(Number(1))", 2, 3)]);
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn numbers() {
+        let (tokens, errors) = lex("0x_ff_u8 12ab");
+        assert_eq!(tokens, vec![tok("Num(TypedNumber(255, \"u8\"))", 0, 8), tok("Num(Error)", 9, 13)]);
+        assert_eq!(errors, vec![tok("invalid digit or suffix in an integer literal", 9, 13)]);
+    }
+
+    #[test]
+    fn raw_strs() {
+        let (tokens, errors) = lex("r#\"a\"b\"# r\"c\"");
+        assert_eq!(tokens, vec![tok("RawStr(\"a\\\"b\")", 0, 8), tok("RawStr(\"c\")", 9, 13)]);
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn unterminated() {
+        let (tokens, errors) = lex("x \"ab");
+        assert_eq!(tokens, vec![tok("Id(\"x\")", 0, 1), tok("Str(\"\")", 2, 5)]);
+        assert_eq!(errors, vec![tok("unterminated str, `\"` is never closed", 2, 3)]);
+
+        let (tokens, errors) = lex("'a\ny");
+        assert_eq!(tokens, vec![tok("Char('\\0')", 0, 3), tok("Id(\"y\")", 3, 4)]);
+        assert_eq!(errors, vec![tok("unterminated char, `'` is never closed", 0, 1)]);
+
+        let (tokens, errors) = lex("x /* a /* b */");
+        assert_eq!(tokens, vec![tok("Id(\"x\")", 0, 1)]);
+        assert_eq!(errors, vec![tok("unterminated comment, `/*` is never closed", 2, 4)]);
+    }
+
+    #[test]
+    fn invalid_escape() {
+        let (tokens, errors) = lex("\"a\\qb\" 1");
+        assert_eq!(tokens, vec![tok("Str(\"\")", 0, 6), tok("Num(Number(1))", 7, 8)]);
+        assert_eq!(errors.len(), 1);
+        assert_eq!((errors[0].1, errors[0].2), (2, 4));
+    }
+}
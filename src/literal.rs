@@ -0,0 +1,70 @@
+use crate::myast::Node;
+
+const SUFFIXES: [&str; 8] = ["i8", "i16", "i32", "i64", "u8", "u16", "u32", "u64"];
+
+// an integer literal, with an optional 0x, 0o or 0b prefix, `_` separators and a type suffix
+pub fn int(s: &str) -> Result<Node, &'static str> {
+    let s = s.replace('_', "");
+    let (digits, suffix) = match SUFFIXES.iter().find(|suffix| s.ends_with(*suffix)) {
+        Some(suffix) => (&s[..s.len() - suffix.len()], Some(*suffix)),
+        None => (&s[..], None)
+    };
+    let (digits, radix) = match digits.get(..2) {
+        Some("0x") => (&digits[2..], 16),
+        Some("0o") => (&digits[2..], 8),
+        Some("0b") => (&digits[2..], 2),
+        _ => (digits, 10)
+    };
+    if digits.is_empty() {
+        return Err("integer literal without digits");
+    }
+    if !digits.chars().all(|c| c.is_digit(radix)) {
+        return Err("invalid digit or suffix in an integer literal");
+    }
+    match (u64::from_str_radix(digits, radix), suffix) {
+        (Ok(n), Some(suffix)) => Ok(Node::TypedNumber(n, String::from(suffix))),
+        (Ok(n), None) if n <= i64::MAX as u64 => Ok(Node::Number(n as i64)),
+        (Ok(n), None) => Ok(Node::BigNumber(n.to_string())),
+        // decimal literals of any size are bigints
+        (Err(_), None) if radix == 10 => Ok(Node::BigNumber(String::from(digits.trim_start_matches('0')))),
+        (Err(_), _) => Err("integer literal is too large")
+    }
+}
+
+// the text between the quotes of a str or char literal with its escapes replaced,
+// an error has the offset of the invalid escape
+pub fn unescape(s: &str) -> Result<String, (&'static str, usize)> {
+    let mut res = String::with_capacity(s.len());
+    let mut chars = s.char_indices();
+    while let Some((start, c)) = chars.next() {
+        if c != '\\' {
+            res.push(c);
+            continue;
+        }
+        let c = match chars.next().map(|(_, c)| c) {
+            Some('n') => '\n',
+            Some('r') => '\r',
+            Some('t') => '\t',
+            Some('0') => '\0',
+            Some('\\') => '\\',
+            Some('"') => '"',
+            Some('\'') => '\'',
+            // `\u{1F600}`
+            Some('u') => {
+                let rest = &s[start + 2..];
+                let code = match (rest.starts_with('{'), rest.find('}')) {
+                    (true, Some(end)) => &rest[1..end],
+                    _ => return Err(("a unicode escape is written as `\\u{...}`", start))
+                };
+                chars.nth(code.len() + 1);
+                match u32::from_str_radix(code, 16).ok().and_then(std::char::from_u32) {
+                    Some(c) if code.len() <= 6 => c,
+                    _ => return Err(("invalid unicode escape", start))
+                }
+            },
+            _ => return Err(("invalid escape sequence", start))
+        };
+        res.push(c);
+    }
+    Ok(res)
+}
@@ -1,3 +1,7 @@
+// natives take the raw pointers of the JIT code, and the visitors take the nodes as they're
+// stored in the AST
+#![allow(clippy::not_unsafe_ptr_arg_deref, clippy::missing_safety_doc, clippy::borrowed_box, clippy::ptr_arg,
+    clippy::new_without_default)]
 extern crate libc;
 extern crate either;
 pub mod bindings;
@@ -6,32 +10,184 @@ pub mod wrapper;
 pub mod codegen;
 pub mod types;
 pub mod stdlib;
+pub mod optimize;
+pub mod runtime;
+pub mod source;
+pub mod bigint;
+pub mod collections;
+pub mod format;
+pub mod literal;
+pub mod lexer;
+pub mod pretty;
 
 #[macro_use] extern crate lalrpop_util;
-lalrpop_mod!(pub grammar);
+// generated code
+lalrpop_mod!(#[allow(clippy::all)] pub grammar);
 
-use codegen::Builder;
-use wrapper::Value;
+use codegen::{Builder, DEFAULT_TIER_THRESHOLD};
+use lexer::{Lexer, SyntaxError};
+use myast::Node;
+use optimize::OptLevel;
+use source::Source;
 use std::env;
+use std::mem;
+
+// the parser skips to the next `;` or `}` after a syntax error, so every error is found,
+// they're sorted by their position. the comments and blank lines are kept for `rlan fmt`
+fn parse(text: &str, keep_trivia: bool) -> Result<Vec<Node>, Vec<SyntaxError>> {
+    let mut lexer = Lexer::new(text);
+    let tokens: Vec<_> = (&mut lexer).collect();
+    let mut trivia = if keep_trivia { mem::take(&mut lexer.trivia) } else { Vec::new() };
+    let mut recovered = Vec::new();
+    let res = grammar::CodeParser::new().parse(&mut recovered, &mut trivia, tokens);
+    let mut errors = lexer.errors;
+    errors.extend(recovered.into_iter().map(|e| SyntaxError::from(e.error)));
+    let parsed = match res {
+        Ok(parsed) => parsed,
+        Err(err) => {
+            errors.push(SyntaxError::from(err));
+            Vec::new()
+        }
+    };
+    for n in &parsed {
+        format::check_positions(n, &mut errors);
+    }
+    if errors.is_empty() {
+        return Ok(parsed);
+    }
+    errors.sort_by_key(|err| err.span.lo);
+    Err(errors)
+}
+
+// nothing is built after a syntax error, the code has a Node::Error in place of what the
+// parser skipped and would only give more confusing errors
+fn report(source: &Source, errors: &[SyntaxError]) -> ! {
+    for err in errors {
+        eprintln!("{}: error: {}", source.location(err.span.lo), err.msg);
+    }
+    std::process::exit(1);
+}
+
+// `rlan fmt files` formats the files in place, with `--check` it only lists
+// the ones that aren't formatted and fails if there are any
+fn fmt(args: &[String]) {
+    let check = args.iter().any(|a| a == "--check");
+    // not 1, which `--check` uses for unformatted files
+    if args.iter().all(|a| a == "--check") {
+        eprintln!("Error: no files to format, the usage is `rlan fmt [--check] files`");
+        std::process::exit(2);
+    }
+    let mut unformatted = false;
+    for file in args.iter().filter(|a| *a != "--check") {
+        let code: String = std::fs::read_to_string(file).unwrap();
+        let source = Source::new(file.clone(), code);
+        let parsed = parse(&source.text, true).unwrap_or_else(|errors| report(&source, &errors));
+        let formatted = pretty::format(&source.text, &parsed);
+        if formatted == source.text {
+            continue;
+        }
+        if check {
+            println!("{}", file);
+            unformatted = true;
+        } else {
+            std::fs::write(file, formatted).unwrap();
+        }
+    }
+    std::process::exit(if unformatted { 1 } else { 0 });
+}
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        panic!("Error: the program requires one argument -  the file name");
+    if args.get(1).map(|a| a.as_str()) == Some("fmt") {
+        return fmt(&args[2..]);
+    }
+    // `rlan run` is the same as `rlan`
+    let args = if args.get(1).map(|a| a.as_str()) == Some("run") { &args[2..] } else { &args[1..] };
+    let mut file: Option<&String> = None;
+    let mut script_args: Vec<String> = Vec::new();
+    let mut tier_threshold: Option<i64> = None;
+    let mut lazy = true;
+    let mut opt_level = OptLevel::O2;
+    let mut checked = cfg!(debug_assertions);
+    for arg in args {
+        if file.is_some() {
+            // everything after the file name belongs to the script
+            script_args.push(arg.clone());
+        } else if let Some(level) = OptLevel::from_flag(arg) {
+            opt_level = level;
+        } else if arg == "--checked-arithmetic" {
+            checked = true;
+        } else if arg == "--wrapping-arithmetic" {
+            checked = false;
+        } else if arg == "--eager" {
+            lazy = false;
+        } else if arg == "--tiered" {
+            // compile at libjit's level 0 and recompile the hot functions at the full level
+            tier_threshold = Some(DEFAULT_TIER_THRESHOLD);
+        } else if let Some(n) = arg.strip_prefix("--tier-threshold=") {
+            let n = n.parse().expect("Error: invalid tier threshold");
+            tier_threshold = Some(n);
+        } else if arg.starts_with("--") {
+            eprintln!("Error: unknown option {}", arg);
+            std::process::exit(1);
+        } else {
+            file = Some(arg);
+        }
+    }
+    let file = file.expect("Error: the program requires one argument -  the file name");
+    let code: String = std::fs::read_to_string(file).unwrap();
+    let source = Source::new(file.clone(), code);
+    let parsed = parse(&source.text, false).unwrap_or_else(|errors| report(&source, &errors));
+    let parsed = optimize::optimize(parsed, opt_level);
+    runtime::set_args(script_args);
+    // the AST, the IR of the functions and the result go to stderr
+    let debug = env::var_os("RLAN_DEBUG").is_some();
+    if debug {
+        eprintln!("{:#?}", parsed);
     }
-    let code: String = std::fs::read_to_string(&args[1]).unwrap();
-    let parser = grammar::CodeParser::new();
-    let parsed = parser.parse(&code).unwrap();
-    #[cfg(debug_assertions)]
-    println!("{:#?}", parsed);
 
     let mut builder = Builder::new();
-    let mut val = Value::constant_long(&builder.main, 0);
-    for n in parsed {
-        val = builder.visit(&n);
-    };
-    builder.main.i_return(&val);
-    let res = builder.execute();
-    #[cfg(debug_assertions)]
-    println!("result = {}", res);
+    builder.tier_threshold = tier_threshold;
+    builder.lazy = lazy;
+    builder.opt_level = opt_level;
+    builder.checked = checked;
+    builder.debug = debug;
+    builder.build_main(&parsed);
+    match builder.execute() {
+        Ok(res) => {
+            if debug {
+                eprintln!("result = {}", res);
+            }
+            // the int the script ends with is its exit status
+            runtime::flush_all();
+            std::process::exit(res as i32);
+        },
+        Err(exc) => {
+            runtime::flush_all();
+            eprintln!("Uncaught exception: {}", exc);
+            for (name, offset) in builder.stack_trace(&exc) {
+                match offset {
+                    Some(offset) => eprintln!("  at {} ({})", name, source.location(offset)),
+                    None => eprintln!("  at {}", name)
+                }
+            }
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn syntax_errors_stop_before_codegen() {
+        // the use of the broken definition isn't built, so it doesn't add an error
+        let errors = parse("def f x:int -> int { <- x + };\nprintint (f 1)", false).err().unwrap();
+        assert_eq!(errors.len(), 1);
+        assert_eq!((errors[0].span.lo, errors[0].span.hi), (28, 29));
+
+        let errors = parse("let a = ;\nlet b = 1 +;\nprintint (a + b)", false).err().unwrap();
+        assert_eq!(errors.len(), 2);
+    }
 }
@@ -1,15 +1,95 @@
 #[derive(Clone, Debug)]
 pub enum Node {
     Empty,
-    BinOp(Box<Node>, Op, Box<Node>),
+    Error, // in place of code with a syntax error
+    Unit, // ()
+    BinOp(Box<Node>, Op, Box<Node>, Span),
     Number(i64),
+    Bool(bool),
+    BigNumber(String), // integer literal out of the range of int
+    TypedNumber(u64, String), // integer literal with a type suffix
     StrLiteral(String),
+    CharLiteral(char),
     Ident(String),
-    Call(Vec<Node>),
+    Call(Vec<Node>, Span),
     VarDef(String, Box<Node>),
-    FuncDef(String, Vec<(String, String)>, String, Vec<Node>), // funcname, (argname, argtype), rettype, body
+    FuncDef(String, Vec<(String, TypeExpr)>, TypeExpr, Vec<Node>), // funcname, (argname, argtype), rettype, body
+    GenericDef(String, Vec<String>, Vec<(String, TypeExpr)>, TypeExpr, Vec<Node>), // like FuncDef, with type parameters
     If(Box<Node>, Box<Node>, Box<Node>),
-    Ret(Box<Node>)
+    Ret(Box<Node>),
+    Raise(Box<Node>, Span),
+    Try(Vec<Node>, String, Vec<Node>), // body, name of the caught error code, handler
+    Cast(Box<Node>, TypeExpr, Span), // value, target type
+    For(String, Box<Node>, Vec<Node>), // name of the element, iterated value, body
+    Tuple(Vec<Node>),
+    Destructure(Vec<String>, Box<Node>), // let (a, b) = tuple
+    Trait(String, Vec<MethodSig>),
+    Impl(String, TypeExpr, Vec<Node>), // trait, type, FuncDefs of the methods with `self` as the first argument
+    MethodCall(Box<Node>, String, Vec<Node>, Span), // receiver, method, args
+    Const(String, Box<Node>),
+    Global(String, Box<Node>),
+    Assign(String, Box<Node>), // to a global
+    VecLit(Vec<Node>),
+    MapLit(Vec<(Node, Node)>),
+    Format(Vec<FmtPart>, Span), // str literal with placeholders
+    Trivia(Trivia), // only kept in the statements parsed for `rlan fmt`
+}
+
+// what the lexer skips between statements
+#[derive(Clone, Debug)]
+pub enum Trivia {
+    Comment(String, bool), // the text with its `#` or `/* */`, whether it comes after code on its line
+    Blank, // one or more empty lines
+}
+
+// piece of a str literal with placeholders
+#[derive(Clone, Debug)]
+pub enum FmtPart {
+    Lit(String),
+    Arg(Box<Node>, FmtSpec), // `{x:spec}`
+    Pos(Option<usize>, FmtSpec), // `{}` or `{0}`, filled by the arguments of `format`
+}
+
+// `[[fill]align][0][width][.precision][x|X|o|b]` after the `:` of a placeholder
+#[derive(Clone, Debug)]
+pub struct FmtSpec {
+    pub fill: char,
+    pub align: Option<Align>, // numbers are aligned right and everything else left by default
+    pub zero: bool,
+    pub width: usize,
+    pub precision: Option<usize>,
+    pub radix: u32,
+    pub upper: bool,
+}
+
+impl Default for FmtSpec {
+    fn default() -> Self {
+        FmtSpec {fill: ' ', align: None, zero: false, width: 0, precision: None, radix: 10, upper: false}
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Align {
+    Left,
+    Right,
+    Center,
+}
+
+// method of a trait: name, arguments after `self`, return type
+pub type MethodSig = (String, Vec<(String, TypeExpr)>, TypeExpr);
+
+// a type as written in the source
+#[derive(Clone, Debug, PartialEq)]
+pub enum TypeExpr {
+    Named(String),
+    Tuple(Vec<TypeExpr>),
+    Generic(String, Vec<TypeExpr>), // Vec[T], Map[K, V]
+}
+
+impl TypeExpr {
+    pub fn is_void(&self) -> bool {
+        *self == TypeExpr::Named(String::from("void"))
+    }
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -26,4 +106,16 @@ pub enum Op {
     Gre, // >=
     And, // and
     Or,  // or
-}
\ No newline at end of file
+}
+// byte offsets into the source code
+#[derive(Clone, Copy, Debug)]
+pub struct Span {
+    pub lo: usize,
+    pub hi: usize,
+}
+
+impl Span {
+    pub fn new(lo: usize, hi: usize) -> Self {
+        Span {lo, hi}
+    }
+}
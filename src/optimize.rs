@@ -0,0 +1,320 @@
+use crate::myast::{Node, Op, Span, TypeExpr, FmtPart};
+use crate::wrapper::Function;
+
+use std::collections::HashMap;
+
+// functions whose body has more nodes than this are never inlined
+const INLINE_LIMIT: usize = 16;
+
+// self tail calls aren't an AST pass, codegen turns them into loops at every level
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub enum OptLevel {
+    O0, // no AST passes, no libjit optimizations
+    O1, // constant folding and dead branch elimination
+    O2, // + inlining of small functions
+}
+
+impl OptLevel {
+    pub fn from_flag(flag: &str) -> Option<OptLevel> {
+        match flag {
+            "-O0" => Some(OptLevel::O0),
+            "-O1" => Some(OptLevel::O1),
+            "-O2" => Some(OptLevel::O2),
+            _ => None
+        }
+    }
+
+    // the level functions are compiled with by libjit
+    pub fn jit_level(&self) -> u32 {
+        match self {
+            OptLevel::O0 => 0,
+            _ => Function::max_optimization_level()
+        }
+    }
+}
+
+// a function whose calls can be replaced by its body
+struct Inlinable {
+    params: Vec<(String, TypeExpr)>,
+    rettype: TypeExpr,
+    body: Node,
+}
+
+struct Optimizer {
+    level: OptLevel,
+    inlinable: HashMap<String, Inlinable>,
+}
+
+pub fn optimize(code: Vec<Node>, level: OptLevel) -> Vec<Node> {
+    if level == OptLevel::O0 {
+        return code;
+    }
+    let mut opt = Optimizer {level, inlinable: HashMap::new()};
+    code.into_iter().map(|n| opt.top_level(n)).collect()
+}
+
+impl Optimizer {
+    fn top_level(&mut self, n: Node) -> Node {
+        match n {
+            Node::FuncDef(name, args, rettype, body) => {
+                // a redefinition replaces the old function from here on
+                self.inlinable.remove(&name);
+                let body: Vec<Node> = body.into_iter().map(|n| self.expr(n)).collect();
+                if self.level >= OptLevel::O2 {
+                    self.consider_inlining(&name, &args, &rettype, &body);
+                }
+                Node::FuncDef(name, args, rettype, body)
+            },
+            Node::Const(name, val) => Node::Const(name, Box::new(self.expr(*val))),
+            Node::Global(name, val) => Node::Global(name, Box::new(self.expr(*val))),
+            Node::GenericDef(name, params, args, rettype, body) => {
+                self.inlinable.remove(&name);
+                let body = body.into_iter().map(|n| self.expr(n)).collect();
+                Node::GenericDef(name, params, args, rettype, body)
+            },
+            Node::Impl(name, tp, methods) => {
+                // methods are never inlined, they're only called through the method table
+                let methods = methods.into_iter().map(|m| match m {
+                    Node::FuncDef(mname, args, rettype, body) => {
+                        Node::FuncDef(mname, args, rettype, body.into_iter().map(|n| self.expr(n)).collect())
+                    },
+                    m => m
+                }).collect();
+                Node::Impl(name, tp, methods)
+            },
+            n => self.expr(n)
+        }
+    }
+
+    fn expr(&mut self, n: Node) -> Node {
+        match n {
+            Node::BinOp(lhs, op, rhs, span) => {
+                let lhs = self.expr(*lhs);
+                let rhs = self.expr(*rhs);
+                fold(lhs, op, rhs, span)
+            },
+            Node::Call(ns, span) => {
+                let ns: Vec<Node> = ns.into_iter().map(|n| self.expr(n)).collect();
+                if self.level >= OptLevel::O2 {
+                    if let Some(inlined) = self.inline(&ns, &span) {
+                        return self.expr(inlined);
+                    }
+                }
+                Node::Call(ns, span)
+            },
+            Node::VarDef(name, val) => Node::VarDef(name, Box::new(self.expr(*val))),
+            Node::Assign(name, val) => Node::Assign(name, Box::new(self.expr(*val))),
+            Node::Destructure(names, val) => Node::Destructure(names, Box::new(self.expr(*val))),
+            Node::Tuple(elems) => Node::Tuple(elems.into_iter().map(|n| self.expr(n)).collect()),
+            Node::VecLit(elems) => Node::VecLit(elems.into_iter().map(|n| self.expr(n)).collect()),
+            Node::MapLit(entries) => Node::MapLit(entries.into_iter().map(|(k, v)| (self.expr(k), self.expr(v))).collect()),
+            Node::Format(parts, span) => Node::Format(parts.into_iter().map(|p| match p {
+                FmtPart::Arg(val, spec) => FmtPart::Arg(Box::new(self.expr(*val)), spec),
+                p => p
+            }).collect(), span),
+            Node::MethodCall(recv, method, args, span) => Node::MethodCall(
+                Box::new(self.expr(*recv)),
+                method,
+                args.into_iter().map(|n| self.expr(n)).collect(),
+                span
+            ),
+            Node::If(cond, then, other) => {
+                match self.expr(*cond) {
+                    Node::Bool(true) => self.expr(*then),
+                    Node::Bool(false) => self.expr(*other),
+                    cond => Node::If(Box::new(cond), Box::new(self.expr(*then)), Box::new(self.expr(*other)))
+                }
+            },
+            Node::Ret(val) => Node::Ret(Box::new(self.expr(*val))),
+            Node::Cast(val, typename, span) => Node::Cast(Box::new(self.expr(*val)), typename, span),
+            Node::For(name, iter, body) => Node::For(
+                name,
+                Box::new(self.expr(*iter)),
+                body.into_iter().map(|n| self.expr(n)).collect()
+            ),
+            Node::Raise(val, span) => Node::Raise(Box::new(self.expr(*val)), span),
+            Node::Try(body, name, handler) => Node::Try(
+                body.into_iter().map(|n| self.expr(n)).collect(),
+                name,
+                handler.into_iter().map(|n| self.expr(n)).collect()
+            ),
+            n => n
+        }
+    }
+
+    // remember the function if its body is a single small expression
+    fn consider_inlining(&mut self, name: &String, args: &Vec<(String, TypeExpr)>, rettype: &TypeExpr, body: &Vec<Node>) {
+        let body = match body.as_slice() {
+            [Node::Ret(e)] => &**e,
+            [e] if rettype.is_void() => e,
+            _ => return
+        };
+        if size(body) > INLINE_LIMIT || !is_simple(body, name, args) {
+            return;
+        }
+        self.inlinable.insert(name.clone(), Inlinable {
+            params: args.clone(),
+            rettype: rettype.clone(),
+            body: body.clone(),
+        });
+    }
+
+    // the arguments and the result are cast to the declared types, which a call converts them to
+    fn inline(&self, name_and_args: &Vec<Node>, span: &Span) -> Option<Node> {
+        let func = match &name_and_args[0] {
+            Node::Ident(name) => self.inlinable.get(name)?,
+            _ => return None
+        };
+        let args = &name_and_args[1..];
+        // arguments may be evaluated any number of times, so they can't have side effects
+        if args.len() != func.params.len() || !args.iter().all(is_pure) {
+            return None;
+        }
+        let args: Vec<Node> = func.params.iter().zip(args.iter())
+            .map(|((_, tp), arg)| Node::Cast(Box::new(arg.clone()), tp.clone(), *span))
+            .collect();
+        let bindings: HashMap<&String, &Node> = func.params.iter().map(|(param, _)| param).zip(args.iter()).collect();
+        let body = substitute(&func.body, &bindings);
+        if func.rettype.is_void() {
+            Some(body)
+        } else {
+            Some(Node::Cast(Box::new(body), func.rettype.clone(), *span))
+        }
+    }
+}
+
+// the value of a constant expression, after substituting the constants defined before it
+pub fn eval_const(n: &Node, consts: &HashMap<String, Node>) -> Option<Node> {
+    let bindings: HashMap<&String, &Node> = consts.iter().collect();
+    let mut opt = Optimizer {level: OptLevel::O1, inlinable: HashMap::new()};
+    match opt.expr(substitute(n, &bindings)) {
+        n @ Node::Unit | n @ Node::Number(_) | n @ Node::Bool(_) | n @ Node::BigNumber(_) | n @ Node::TypedNumber(..) |
+        n @ Node::StrLiteral(_) | n @ Node::CharLiteral(_) => Some(n),
+        _ => None
+    }
+}
+
+fn fold(lhs: Node, op: Op, rhs: Node, span: Span) -> Node {
+    let folded = match (&lhs, op, &rhs) {
+        // overflowing operations are left for the runtime
+        (Node::Number(a), Op::Add, Node::Number(b)) => a.checked_add(*b).map(Node::Number),
+        (Node::Number(a), Op::Sub, Node::Number(b)) => a.checked_sub(*b).map(Node::Number),
+        (Node::Number(a), Op::Mul, Node::Number(b)) => a.checked_mul(*b).map(Node::Number),
+        (Node::Number(a), Op::Div, Node::Number(b)) => a.checked_div(*b).map(Node::Number),
+        (Node::Number(a), Op::Eql, Node::Number(b)) => Some(Node::Bool(a == b)),
+        (Node::Number(a), Op::Neq, Node::Number(b)) => Some(Node::Bool(a != b)),
+        (Node::Number(a), Op::Lwt, Node::Number(b)) => Some(Node::Bool(a < b)),
+        (Node::Number(a), Op::Lwe, Node::Number(b)) => Some(Node::Bool(a <= b)),
+        (Node::Number(a), Op::Grt, Node::Number(b)) => Some(Node::Bool(a > b)),
+        (Node::Number(a), Op::Gre, Node::Number(b)) => Some(Node::Bool(a >= b)),
+        (Node::Bool(a), Op::And, Node::Bool(b)) => Some(Node::Bool(*a && *b)),
+        (Node::Bool(a), Op::Or, Node::Bool(b)) => Some(Node::Bool(*a || *b)),
+        _ => None
+    };
+    folded.unwrap_or_else(|| Node::BinOp(Box::new(lhs), op, Box::new(rhs), span))
+}
+
+fn size(n: &Node) -> usize {
+    match n {
+        Node::BinOp(lhs, _, rhs, _) => 1 + size(lhs) + size(rhs),
+        Node::Call(ns, _) => 1 + ns.iter().map(size).sum::<usize>(),
+        Node::Cast(val, _, _) => 1 + size(val),
+        Node::Tuple(elems) => 1 + elems.iter().map(size).sum::<usize>(),
+        _ => 1
+    }
+}
+
+// an expression that can be inlined, calls to `name` would make it recursive. the only variables
+// can be the parameters, a global or a const would be captured by a local of the caller
+fn is_simple(n: &Node, name: &String, params: &[(String, TypeExpr)]) -> bool {
+    match n {
+        Node::Unit | Node::Number(_) | Node::Bool(_) | Node::BigNumber(_) | Node::TypedNumber(..) | Node::StrLiteral(_) | Node::CharLiteral(_) => true,
+        Node::Ident(id) => params.iter().any(|(param, _)| param == id),
+        Node::BinOp(lhs, _, rhs, _) => is_simple(lhs, name, params) && is_simple(rhs, name, params),
+        Node::Cast(val, _, _) => is_simple(val, name, params),
+        Node::Tuple(elems) => elems.iter().all(|n| is_simple(n, name, params)),
+        Node::Call(ns, _) => match &ns[0] {
+            Node::Ident(id) if id != name => ns[1..].iter().all(|n| is_simple(n, name, params)),
+            _ => false
+        },
+        _ => false
+    }
+}
+
+fn is_pure(n: &Node) -> bool {
+    match n {
+        Node::Unit | Node::Number(_) | Node::Bool(_) | Node::BigNumber(_) | Node::TypedNumber(..) | Node::StrLiteral(_) | Node::CharLiteral(_) | Node::Ident(_) => true,
+        Node::BinOp(lhs, _, rhs, _) => is_pure(lhs) && is_pure(rhs),
+        Node::Cast(val, _, _) => is_pure(val),
+        Node::Tuple(elems) => elems.iter().all(is_pure),
+        _ => false
+    }
+}
+
+fn substitute(n: &Node, bindings: &HashMap<&String, &Node>) -> Node {
+    match n {
+        Node::Ident(id) => match bindings.get(id) {
+            Some(arg) => (*arg).clone(),
+            None => n.clone()
+        },
+        Node::BinOp(lhs, op, rhs, span) => Node::BinOp(
+            Box::new(substitute(lhs, bindings)), *op, Box::new(substitute(rhs, bindings)), *span
+        ),
+        Node::Cast(val, typename, span) => Node::Cast(Box::new(substitute(val, bindings)), typename.clone(), *span),
+        Node::Tuple(elems) => Node::Tuple(elems.iter().map(|n| substitute(n, bindings)).collect()),
+        // the function name itself is never a parameter
+        Node::Call(ns, span) => Node::Call(
+            ns[..1].iter().cloned().chain(ns[1..].iter().map(|n| substitute(n, bindings))).collect(),
+            *span
+        ),
+        _ => n.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codegen::Builder;
+    use crate::grammar::CodeParser;
+    use crate::lexer::Lexer;
+
+    fn parse(code: &str) -> Vec<Node> {
+        CodeParser::new().parse(&mut Vec::new(), &mut Vec::new(), Lexer::new(code)).unwrap()
+    }
+
+    fn optimized(code: &str) -> String {
+        format!("{:?}", optimize(parse(code), OptLevel::O2))
+    }
+
+    // the exit status of a script built at `level`
+    fn run(code: &str, level: OptLevel) -> i64 {
+        let mut builder = Builder::new();
+        builder.opt_level = level;
+        builder.build_main(&optimize(parse(code), level));
+        builder.execute().ok().expect("Uncaught exception")
+    }
+
+    #[test]
+    fn inlines_small_functions() {
+        let code = optimized("def add x:int -> int <- x + 1; def f -> int { <- add 2 }");
+        assert!(!code.contains("Call"), "{}", code);
+    }
+
+    #[test]
+    fn globals_are_not_inlined() {
+        let code = optimized("global counter = 1; def add x:int -> int <- x + counter;
+            def f -> int { let counter = 5; <- add 2 }");
+        assert!(code.contains("Call([Ident(\"add\")"), "{}", code);
+    }
+
+    #[test]
+    fn inlining_keeps_the_declared_types() {
+        let code = optimized("def sq x:int -> int <- x * x; let a = 3 as i32; printint (sq a)");
+        assert!(code.contains("Cast(Ident(\"a\"), Named(\"int\")"), "{}", code);
+
+        let code = "def sq x:int -> int <- x * x; def half x:i32 -> i32 <- x / 2;
+            let a = 3 as i32; let b = 9 as i16; printint (sq a); sq a + (half b) as int";
+        assert_eq!(run(code, OptLevel::O0), 13);
+        assert_eq!(run(code, OptLevel::O2), 13);
+    }
+}
@@ -0,0 +1,519 @@
+use crate::lexer::{Lexer, Token, TokenKind};
+use crate::myast::{Node, Op, Span, Trivia, TypeExpr};
+
+// puts the trivia from `lo` to `hi` that no inner list has taken between the statements, before
+// the statement they come before. the ones inside a statement, like in the middle of an
+// expression, go on their own line before it
+pub fn attach(trivia: &mut Vec<(usize, Trivia)>, lo: usize, stmts: Vec<(usize, Node, usize)>, hi: usize) -> Vec<Node> {
+    let mut res = Vec::with_capacity(stmts.len());
+    for (start, stmt, end) in stmts {
+        take(trivia, lo, start, end, &mut res);
+        res.push(stmt);
+    }
+    take(trivia, lo, hi, hi, &mut res);
+    res
+}
+
+// the trivia from `lo` to `end` of a statement starting at `start`
+fn take(trivia: &mut Vec<(usize, Trivia)>, lo: usize, start: usize, end: usize, res: &mut Vec<Node>) {
+    let first = trivia.iter().position(|(pos, _)| *pos >= lo).unwrap_or(trivia.len());
+    let last = trivia[first..].iter().position(|(pos, _)| *pos >= end).map_or(trivia.len(), |n| first + n);
+    for (pos, t) in trivia.drain(first..last) {
+        match t {
+            Trivia::Blank if pos > start => {},
+            Trivia::Comment(text, _) if pos > start => res.push(Node::Trivia(Trivia::Comment(text, false))),
+            t => res.push(Node::Trivia(t))
+        }
+    }
+}
+
+// the loosest expression that can be written in a place without parentheses, from the grammar
+#[derive(Clone, Copy, PartialEq, PartialOrd)]
+enum Prec {
+    Stmt, // IfExpr
+    Ret, // RetExpr
+    Pipe,
+    Logic, // Expr
+    Cmp,
+    Sum, // ValExpr
+    Product, // Term
+    Cast,
+    Call,
+    Postfix, // method calls and atoms
+}
+
+// the source of a parsed script in the canonical style: blocks indented by 4 spaces, one
+// statement per line, spaces around operators and only the parentheses the precedence needs.
+// literals are written as they are in the source
+pub fn format(text: &str, code: &[Node]) -> String {
+    let mut lexer = Lexer::new(text);
+    let mut tokens = Vec::new();
+    while let Some(token) = lexer.next_token() {
+        tokens.push(token);
+    }
+    let mut printer = Printer {text, tokens, next_literal: 0, out: String::new(), indent: 0};
+    printer.stmts(code);
+    if !printer.out.is_empty() {
+        printer.out.push('\n');
+    }
+    printer.out
+}
+
+struct Printer<'a> {
+    text: &'a str,
+    tokens: Vec<Token>,
+    next_literal: usize, // the literal tokens before it are printed
+    out: String,
+    indent: usize,
+}
+
+impl<'a> Printer<'a> {
+    fn write(&mut self, s: &str) {
+        self.out.push_str(s);
+    }
+
+    fn newline(&mut self) {
+        let len = self.out.trim_end_matches(' ').len();
+        self.out.truncate(len);
+        self.out.push('\n');
+        for _ in 0..self.indent {
+            self.out.push_str("    ");
+        }
+    }
+
+    // `;` between the statements, a comment after code stays on its line
+    // and blank lines are kept, but not at the start or the end
+    fn stmts(&mut self, nodes: &[Node]) {
+        let last = nodes.iter().rposition(|n| !is_trivia(n));
+        let mut first = true;
+        let mut blank = false;
+        for (i, node) in nodes.iter().enumerate() {
+            match node {
+                Node::Trivia(Trivia::Blank) => blank = !first,
+                Node::Trivia(Trivia::Comment(text, true)) if !self.out.is_empty() => {
+                    self.write(" ");
+                    self.write(text);
+                },
+                node => {
+                    if !self.out.is_empty() {
+                        if blank {
+                            self.newline();
+                        }
+                        self.newline();
+                    }
+                    first = false;
+                    blank = false;
+                    match node {
+                        Node::Trivia(Trivia::Comment(text, _)) => {
+                            self.write(text);
+                            continue;
+                        },
+                        Node::If(cond, e1, e2) => self.if_stmt(cond, e1, e2),
+                        node => self.node(node)
+                    }
+                    if last.is_some_and(|last| i < last) {
+                        self.write(";");
+                    }
+                }
+            }
+        }
+    }
+
+    fn block(&mut self, nodes: &[Node]) {
+        if nodes.iter().all(|n| matches!(n, Node::Trivia(Trivia::Blank))) {
+            return self.write("{}");
+        }
+        self.write("{");
+        self.indent += 1;
+        self.stmts(nodes);
+        self.indent -= 1;
+        self.newline();
+        self.write("}");
+    }
+
+    // an if on its own line has its branches on the next lines, and `else if` chains
+    fn if_stmt(&mut self, cond: &Node, e1: &Node, e2: &Node) {
+        self.write("if ");
+        self.expr(cond, Prec::Ret);
+        self.write(":");
+        self.branch(e1);
+        self.newline();
+        self.write("else");
+        match e2 {
+            Node::If(cond, e1, e2) => {
+                self.write(" ");
+                self.if_stmt(cond, e1, e2);
+            },
+            e2 => self.branch(e2)
+        }
+    }
+
+    fn branch(&mut self, node: &Node) {
+        self.indent += 1;
+        self.newline();
+        match node {
+            Node::If(cond, e1, e2) => self.if_stmt(cond, e1, e2),
+            node => self.node(node)
+        }
+        self.indent -= 1;
+    }
+
+    fn expr(&mut self, node: &Node, min: Prec) {
+        if self.prec(node) < min {
+            self.write("(");
+            self.node(node);
+            self.write(")");
+        } else {
+            self.node(node);
+        }
+    }
+
+    // an argument of a call can't start with a map literal
+    fn arg(&mut self, node: &Node) {
+        if starts_with_map(node) {
+            self.write("(");
+            self.node(node);
+            self.write(")");
+        } else {
+            self.expr(node, Prec::Postfix);
+        }
+    }
+
+    fn comma(&mut self, nodes: &[Node]) {
+        for (i, node) in nodes.iter().enumerate() {
+            if i > 0 {
+                self.write(", ");
+            }
+            self.expr(node, Prec::Stmt);
+        }
+    }
+
+    fn node(&mut self, node: &Node) {
+        match node {
+            Node::Empty => {},
+            Node::Unit => self.write("()"),
+            Node::BinOp(lhs, op, rhs, _) => {
+                let (l, r, text) = match op {
+                    Op::And => (Prec::Cmp, Prec::Logic, "and"),
+                    Op::Or => (Prec::Cmp, Prec::Logic, "or"),
+                    Op::Eql => (Prec::Sum, Prec::Sum, "=="),
+                    Op::Neq => (Prec::Sum, Prec::Sum, "!="),
+                    Op::Lwt => (Prec::Sum, Prec::Sum, "<"),
+                    Op::Lwe => (Prec::Sum, Prec::Sum, "<="),
+                    Op::Grt => (Prec::Sum, Prec::Sum, ">"),
+                    Op::Gre => (Prec::Sum, Prec::Sum, ">="),
+                    // `+`, `-`, `*` and `/` group to the right
+                    Op::Add => (Prec::Product, Prec::Sum, "+"),
+                    Op::Sub => (Prec::Product, Prec::Sum, "-"),
+                    Op::Mul => (Prec::Cast, Prec::Product, "*"),
+                    Op::Div => (Prec::Cast, Prec::Product, "/"),
+                };
+                self.expr(lhs, l);
+                self.write(" ");
+                self.write(text);
+                self.write(" ");
+                self.expr(rhs, r);
+            },
+            Node::Number(_) | Node::BigNumber(_) | Node::TypedNumber(..) | Node::StrLiteral(_) |
+            Node::CharLiteral(_) | Node::Format(..) => self.literal(),
+            Node::Bool(b) => self.write(if *b { "true" } else { "false" }),
+            Node::Ident(name) => self.write(name),
+            // `x |> f a` is parsed as `f x a`
+            Node::Call(ns, span) if self.is_pipe(*span) => {
+                self.expr(&ns[1], Prec::Pipe);
+                self.write(" |> ");
+                self.expr(&ns[0], Prec::Postfix);
+                for arg in &ns[2..] {
+                    self.write(" ");
+                    self.arg(arg);
+                }
+            },
+            Node::Call(ns, _) => {
+                self.expr(&ns[0], Prec::Postfix);
+                if ns.len() == 1 {
+                    self.write(" ()");
+                }
+                for arg in &ns[1..] {
+                    self.write(" ");
+                    self.arg(arg);
+                }
+            },
+            Node::VarDef(name, e) => {
+                self.write("let ");
+                self.write(name);
+                self.write(" = ");
+                self.expr(e, Prec::Stmt);
+            },
+            Node::FuncDef(name, args, rettype, body) => self.def(name, &[], args, rettype, body),
+            Node::GenericDef(name, params, args, rettype, body) => self.def(name, params, args, rettype, body),
+            // inside an expression an if is on one line
+            Node::If(cond, e1, e2) => {
+                self.write("if ");
+                self.expr(cond, Prec::Ret);
+                self.write(": ");
+                self.expr(e1, Prec::Stmt);
+                self.write(" else ");
+                self.expr(e2, Prec::Stmt);
+            },
+            Node::Ret(e) => {
+                self.write("<- ");
+                self.expr(e, Prec::Pipe);
+            },
+            Node::Raise(e, _) => {
+                self.write("raise ");
+                self.expr(e, Prec::Pipe);
+            },
+            Node::Try(body, name, handler) => {
+                self.write("try ");
+                self.block(body);
+                self.write(" catch ");
+                self.write(name);
+                self.write(" ");
+                self.block(handler);
+            },
+            Node::Cast(e, t, _) => {
+                self.expr(e, Prec::Cast);
+                self.write(" as ");
+                self.write(&type_expr(t));
+            },
+            Node::For(name, e, body) => {
+                self.write("for ");
+                self.write(name);
+                self.write(" in ");
+                self.expr(e, Prec::Ret);
+                self.write(" ");
+                self.block(body);
+            },
+            Node::Tuple(es) => {
+                self.write("(");
+                self.comma(es);
+                self.write(")");
+            },
+            Node::Destructure(names, e) => {
+                self.write(&format!("let ({}) = ", names.join(", ")));
+                self.expr(e, Prec::Stmt);
+            },
+            Node::Trait(name, methods) => {
+                self.write(&format!("trait {} ", name));
+                if methods.is_empty() {
+                    return self.write("{}");
+                }
+                self.write("{");
+                self.indent += 1;
+                for (i, (name, args, rettype)) in methods.iter().enumerate() {
+                    self.newline();
+                    self.write(&format!("def {} self{} -> {}", name, self.args(args), type_expr(rettype)));
+                    if i + 1 < methods.len() {
+                        self.write(";");
+                    }
+                }
+                self.indent -= 1;
+                self.newline();
+                self.write("}");
+            },
+            Node::Impl(tr, tp, methods) => {
+                self.write(&format!("impl {} for {} ", tr, type_expr(tp)));
+                self.block(methods);
+            },
+            Node::MethodCall(recv, method, args, _) => {
+                self.expr(recv, Prec::Postfix);
+                self.write(&format!(".{}(", method));
+                self.comma(args);
+                self.write(")");
+            },
+            Node::Const(name, e) => {
+                self.write(&format!("const {} = ", name));
+                self.expr(e, Prec::Stmt);
+            },
+            Node::Global(name, e) => {
+                self.write(&format!("global {} = ", name));
+                self.expr(e, Prec::Stmt);
+            },
+            Node::Assign(name, e) => {
+                self.write(&format!("{} = ", name));
+                self.expr(e, Prec::Stmt);
+            },
+            Node::VecLit(es) => {
+                self.write("[");
+                self.comma(es);
+                self.write("]");
+            },
+            Node::MapLit(entries) if entries.is_empty() => self.write("{:}"),
+            Node::MapLit(entries) => {
+                self.write("{");
+                for (i, (k, v)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        self.write(", ");
+                    }
+                    self.expr(k, Prec::Logic);
+                    self.write(": ");
+                    self.expr(v, Prec::Stmt);
+                }
+                self.write("}");
+            },
+            Node::Error | Node::Trivia(_) => unreachable!()
+        }
+    }
+
+    // the methods of an impl have `self` as their first argument
+    fn def(&mut self, name: &str, params: &[String], args: &[(String, TypeExpr)], rettype: &TypeExpr, body: &[Node]) {
+        self.write("def ");
+        self.write(name);
+        if !params.is_empty() {
+            self.write(&format!("[{}]", params.join(", ")));
+        }
+        match args.split_first() {
+            Some(((name, _), args)) if name == "self" => {
+                let args = self.args(args);
+                self.write(&format!(" self{}", args));
+            },
+            _ => {
+                let args = self.args(args);
+                self.write(&args);
+            }
+        }
+        self.write(&format!(" -> {} ", type_expr(rettype)));
+        self.block(body);
+    }
+
+    fn args(&self, args: &[(String, TypeExpr)]) -> String {
+        args.iter().map(|(name, t)| format!(" {}:{}", name, type_expr(t))).collect()
+    }
+
+    fn prec(&self, node: &Node) -> Prec {
+        match node {
+            Node::VarDef(..) | Node::FuncDef(..) | Node::GenericDef(..) | Node::If(..) | Node::Try(..) |
+            Node::For(..) | Node::Destructure(..) | Node::Trait(..) | Node::Impl(..) | Node::Const(..) |
+            Node::Global(..) | Node::Assign(..) => Prec::Stmt,
+            Node::Ret(_) | Node::Raise(..) => Prec::Ret,
+            Node::Call(_, span) if self.is_pipe(*span) => Prec::Pipe,
+            Node::Call(..) => Prec::Call,
+            Node::BinOp(_, Op::And, _, _) | Node::BinOp(_, Op::Or, _, _) => Prec::Logic,
+            Node::BinOp(_, Op::Add, _, _) | Node::BinOp(_, Op::Sub, _, _) => Prec::Sum,
+            Node::BinOp(_, Op::Mul, _, _) | Node::BinOp(_, Op::Div, _, _) => Prec::Product,
+            Node::BinOp(..) => Prec::Cmp,
+            Node::Cast(..) => Prec::Cast,
+            _ => Prec::Postfix
+        }
+    }
+
+    // a call made by `|>` has one outside of parentheses
+    fn is_pipe(&self, span: Span) -> bool {
+        let start = match self.tokens.binary_search_by_key(&span.lo, |t| t.span.lo) {
+            Ok(i) | Err(i) => i
+        };
+        let mut depth = 0;
+        for token in self.tokens[start..].iter().take_while(|t| t.span.hi <= span.hi) {
+            match token.kind {
+                TokenKind::LParen | TokenKind::LBracket | TokenKind::LBrace => depth += 1,
+                TokenKind::RParen | TokenKind::RBracket | TokenKind::RBrace => depth -= 1,
+                TokenKind::Pipe if depth == 0 => return true,
+                _ => {}
+            }
+        }
+        false
+    }
+
+    // literals are printed in the order they're in the source
+    fn literal(&mut self) {
+        let tokens = &self.tokens[self.next_literal..];
+        let i = tokens.iter().position(|t| {
+            matches!(t.kind, TokenKind::Num(_) | TokenKind::Str(_) | TokenKind::RawStr(_) | TokenKind::Char(_))
+        }).expect("a literal without its token");
+        let span = tokens[i].span;
+        self.next_literal += i + 1;
+        self.out.push_str(&self.text[span.lo..span.hi]);
+    }
+}
+
+fn is_trivia(node: &Node) -> bool {
+    matches!(node, Node::Trivia(_))
+}
+
+fn starts_with_map(node: &Node) -> bool {
+    match node {
+        Node::MapLit(_) => true,
+        Node::MethodCall(recv, ..) => starts_with_map(recv),
+        _ => false
+    }
+}
+
+fn type_expr(t: &TypeExpr) -> String {
+    let list = |ts: &[TypeExpr]| ts.iter().map(type_expr).collect::<Vec<_>>().join(", ");
+    match t {
+        TypeExpr::Named(name) => name.clone(),
+        TypeExpr::Tuple(ts) => format!("({})", list(ts)),
+        TypeExpr::Generic(name, ts) => format!("{}[{}]", name, list(ts)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grammar::CodeParser;
+    use std::mem;
+
+    fn parse(code: &str, keep_trivia: bool) -> Vec<Node> {
+        let mut lexer = Lexer::new(code);
+        let tokens: Vec<_> = (&mut lexer).collect();
+        let mut trivia = if keep_trivia { mem::take(&mut lexer.trivia) } else { Vec::new() };
+        let mut errors = Vec::new();
+        let parsed = CodeParser::new().parse(&mut errors, &mut trivia, tokens).unwrap();
+        assert!(errors.is_empty() && lexer.errors.is_empty(), "{}", code);
+        parsed
+    }
+
+    // the AST without the spans, which move when the code is formatted
+    fn ast(code: &str) -> String {
+        let debug = format!("{:?}", parse(code, false));
+        let mut res = String::new();
+        let mut rest = &debug[..];
+        while let Some(i) = rest.find(", Span {") {
+            res.push_str(&rest[..i]);
+            rest = &rest[i + rest[i..].find('}').unwrap() + 1..];
+        }
+        res.push_str(rest);
+        res
+    }
+
+    // the formatted code, which must have the same AST and format to itself
+    fn formatted(code: &str) -> String {
+        let res = format(code, &parse(code, true));
+        assert_eq!(ast(&res), ast(code));
+        assert_eq!(format(&res, &parse(&res, true)), res);
+        res
+    }
+
+    #[test]
+    fn pipes_and_casts() {
+        assert_eq!(formatted("def f x:int -> int { <- x |> add   1 |> mul 2 }"),
+            "def f x:int -> int {\n    <- x |> add 1 |> mul 2\n}\n");
+        assert_eq!(formatted("let y = (x as i64)+1 as int;let w = (x + 1) as i64"),
+            "let y = x as i64 + 1 as int;\nlet w = (x + 1) as i64\n");
+    }
+
+    #[test]
+    fn nested_ifs() {
+        assert_eq!(formatted("def g a:bool b:bool -> int {\nif a: if b: <- 1 else <- 2\nelse <- 3 }"),
+            "def g a:bool b:bool -> int {\n    if a:\n        if b:\n            <- 1\n        else\n            <- 2\n    else\n        <- 3\n}\n");
+        assert_eq!(formatted("let z = if a: (if b: 1 else 2) else if c: 3 else 4"),
+            "let z = if a: if b: 1 else 2 else if c: 3 else 4\n");
+    }
+
+    #[test]
+    fn map_args() {
+        assert_eq!(formatted("let m = f ({1: 2, 3: 4}) [1,2]"), "let m = f ({1: 2, 3: 4}) [1, 2]\n");
+    }
+
+    #[test]
+    fn comments() {
+        assert_eq!(formatted("# leading\nlet a = 1; # after a\n\n\n/* block */ let b = 2"),
+            "# leading\nlet a = 1; # after a\n\n/* block */\nlet b = 2\n");
+    }
+
+    #[test]
+    fn test_script() {
+        let code = include_str!("../test.mylang");
+        formatted(code);
+    }
+}
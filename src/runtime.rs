@@ -0,0 +1,305 @@
+use crate::wrapper::{self, StackTrace, RESULT_OVERFLOW, RESULT_ARITHMETIC, RESULT_DIVISION_BY_ZERO, RESULT_COMPILE_ERROR,
+    RESULT_OUT_OF_MEMORY, RESULT_NULL_REFERENCE, RESULT_NULL_FUNCTION, RESULT_OUT_OF_BOUNDS};
+use crate::collections;
+use libc::c_void;
+use std::any::Any;
+use std::cell::RefCell;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+
+// code of exceptions thrown by failed I/O operations
+pub const IO_ERROR: i64 = -100;
+
+// the object thrown by `raise` and by failing natives, JIT code reads `code` directly
+#[repr(C)]
+pub struct Exception {
+    pub code: i64,
+    pub message: String,
+    pub trace: StackTrace,
+}
+
+impl Exception {
+    // must be called where the error happened, to record the stack trace
+    fn create(code: i64, message: String) -> *mut c_void {
+        let exc = Box::new(Exception {code, message, trace: StackTrace::capture()});
+        Box::into_raw(exc) as *mut c_void
+    }
+}
+
+impl fmt::Display for Exception {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} (code {})", self.message, self.code)
+    }
+}
+
+// throw an rlan exception from a native function.
+// nothing on the native's stack is dropped, so values owning memory must be gone before calling this
+pub fn raise(code: i64, message: &str) -> ! {
+    wrapper::exception_throw(Exception::create(code, message.to_string()))
+}
+
+// throw an I/O error, which is consumed so it doesn't leak
+pub fn raise_io(err: io::Error) -> ! {
+    let exc = Exception::create(IO_ERROR, err.to_string());
+    drop(err);
+    wrapper::exception_throw(exc)
+}
+
+pub fn install() {
+    wrapper::exception_set_handler(builtin_handler);
+}
+
+// the exception which escaped the executed code, if any
+pub fn take_uncaught() -> Option<Box<Exception>> {
+    let exc = wrapper::exception_take_last();
+    if exc.is_null() {
+        None
+    } else {
+        Some(unsafe { Box::from_raw(exc as *mut Exception) })
+    }
+}
+
+// errors detected by libjit itself (overflow, division by zero...) become rlan exceptions
+extern "C" fn builtin_handler(kind: i32) -> *mut c_void {
+    let message = match kind {
+        RESULT_OVERFLOW => "overflow",
+        RESULT_ARITHMETIC => "arithmetic error",
+        RESULT_DIVISION_BY_ZERO => "division by zero",
+        RESULT_COMPILE_ERROR => "compilation failed",
+        RESULT_OUT_OF_MEMORY => "out of memory",
+        RESULT_NULL_REFERENCE => "null reference",
+        RESULT_NULL_FUNCTION => "null function",
+        RESULT_OUT_OF_BOUNDS => "index out of bounds",
+        _ => "internal error"
+    };
+    Exception::create(kind as i64, message.to_string())
+}
+
+// called by `raise`
+pub extern "C" fn rlan_exception_new(code: i64) -> *mut c_void {
+    Exception::create(code, String::from("raised"))
+}
+
+// called when entering a catch block, frees the exception and returns its code
+pub extern "C" fn rlan_exception_catch(exc: *mut c_void) -> i64 {
+    wrapper::exception_take_last();
+    let exc = unsafe { Box::from_raw(exc as *mut Exception) };
+    exc.code
+}
+
+// an open file or standard stream, passed around as a pointer to a `Handle`.
+// the standard streams are buffered by Rust, files by the handle.
+// closed handles stay allocated since JIT code may still refer to them
+pub enum Handle {
+    Stdin,
+    Stdout,
+    Stderr,
+    Reader(BufReader<File>),
+    Writer(BufWriter<File>),
+    Closed,
+}
+
+thread_local! {
+    static STD_HANDLES: [*mut c_void; 3] = [
+        Box::into_raw(Box::new(Handle::Stdin)) as *mut c_void,
+        Box::into_raw(Box::new(Handle::Stdout)) as *mut c_void,
+        Box::into_raw(Box::new(Handle::Stderr)) as *mut c_void,
+    ];
+    // files open for writing, flushed before the process exits
+    static WRITERS: RefCell<Vec<*mut Handle>> = const { RefCell::new(Vec::new()) };
+}
+
+impl Handle {
+    pub fn stdin() -> *mut c_void {
+        STD_HANDLES.with(|h| h[0])
+    }
+
+    pub fn stdout() -> *mut c_void {
+        STD_HANDLES.with(|h| h[1])
+    }
+
+    pub fn stderr() -> *mut c_void {
+        STD_HANDLES.with(|h| h[2])
+    }
+
+    // `mode` is "r", "w" or "a" like in C
+    pub fn open(path: &str, mode: &str) -> io::Result<*mut c_void> {
+        let handle = match mode {
+            "r" => Handle::Reader(BufReader::new(File::open(path)?)),
+            "w" => Handle::Writer(BufWriter::new(File::create(path)?)),
+            "a" => Handle::Writer(BufWriter::new(OpenOptions::new().append(true).create(true).open(path)?)),
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid file mode"))
+        };
+        let ptr = Box::into_raw(Box::new(handle));
+        if let Handle::Writer(_) = unsafe { &*ptr } {
+            WRITERS.with(|w| w.borrow_mut().push(ptr));
+        }
+        Ok(ptr as *mut c_void)
+    }
+
+    // the next line without the line break, None at the end of the file
+    pub fn read_line(&mut self) -> io::Result<Option<String>> {
+        let mut line = String::new();
+        let read = match self {
+            Handle::Stdin => io::stdin().lock().read_line(&mut line)?,
+            Handle::Reader(r) => r.read_line(&mut line)?,
+            _ => return Err(not_open("reading"))
+        };
+        if read == 0 {
+            return Ok(None);
+        }
+        if line.ends_with('\n') {
+            line.pop();
+            if line.ends_with('\r') {
+                line.pop();
+            }
+        }
+        Ok(Some(line))
+    }
+
+    pub fn at_eof(&mut self) -> io::Result<bool> {
+        match self {
+            Handle::Stdin => Ok(io::stdin().lock().fill_buf()?.is_empty()),
+            Handle::Reader(r) => Ok(r.fill_buf()?.is_empty()),
+            _ => Err(not_open("reading"))
+        }
+    }
+
+    pub fn write(&mut self, text: &str) -> io::Result<()> {
+        match self {
+            Handle::Stdout => io::stdout().write_all(text.as_bytes()),
+            Handle::Stderr => io::stderr().write_all(text.as_bytes()),
+            Handle::Writer(w) => w.write_all(text.as_bytes()),
+            _ => Err(not_open("writing"))
+        }
+    }
+
+    pub fn close(&mut self) -> io::Result<()> {
+        let ptr = self as *mut Handle;
+        match self {
+            Handle::Stdin | Handle::Stdout | Handle::Stderr => return Ok(()),
+            Handle::Writer(w) => {
+                WRITERS.with(|writers| writers.borrow_mut().retain(|h| *h != ptr));
+                w.flush()?;
+            },
+            _ => {}
+        }
+        *self = Handle::Closed;
+        Ok(())
+    }
+}
+
+fn not_open(what: &str) -> io::Error {
+    io::Error::other(format!("file isn't open for {}", what))
+}
+
+pub unsafe fn get_handle<'a>(h: *mut c_void) -> &'a mut Handle {
+    &mut *(h as *mut Handle)
+}
+
+// write out everything buffered, called before the process exits
+pub fn flush_all() {
+    WRITERS.with(|writers| {
+        for w in writers.borrow().iter() {
+            if let Handle::Writer(w) = unsafe { &mut **w } {
+                let _ = w.flush();
+            }
+        }
+    });
+    let _ = io::stdout().flush();
+}
+
+thread_local! {
+    // the command line arguments after the script's name
+    static ARGS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+}
+
+pub fn set_args(args: Vec<String>) {
+    ARGS.with(|a| *a.borrow_mut() = args);
+}
+
+// `args ()`, a new Vec[str] every time
+pub extern "C" fn rlan_args() -> *mut c_void {
+    let strs = ARGS.with(|a| a.borrow().iter().map(|s| new_str(s.clone()) as u64).collect());
+    collections::new_vec(strs)
+}
+
+thread_local! {
+    // every str, vec, map, bigint and global of the script. nothing is freed while it runs,
+    // there's no garbage collection, the whole heap is freed by `free_heap` when it ends
+    static HEAP: RefCell<Vec<Box<dyn Any>>> = RefCell::new(Vec::new());
+}
+
+// moves a value to the heap, the pointer is valid until `free_heap`
+pub fn alloc<T: 'static>(value: T) -> *mut c_void {
+    let mut boxed = Box::new(value);
+    let ptr = &mut *boxed as *mut T as *mut c_void;
+    HEAP.with(|h| h.borrow_mut().push(boxed));
+    ptr
+}
+
+// called once the script has returned, no JIT code may run after this
+pub fn free_heap() {
+    let heap = HEAP.with(|h| h.replace(Vec::new()));
+    drop(heap);
+}
+
+// zeroed memory for a global variable
+pub fn alloc_global(size: usize) -> *mut c_void {
+    let mut words = vec![0u64; size.div_ceil(8)].into_boxed_slice();
+    let ptr = words.as_mut_ptr() as *mut c_void;
+    HEAP.with(|h| h.borrow_mut().push(Box::new(words)));
+    ptr
+}
+
+// strings are immutable and passed around as pointers to a `String`
+pub fn new_str(s: String) -> *mut c_void {
+    alloc(s)
+}
+
+pub unsafe fn get_str<'a>(s: *mut c_void) -> &'a String {
+    &*(s as *const String)
+}
+
+// length of a str in bytes
+pub extern "C" fn rlan_str_len(s: *mut c_void) -> i64 {
+    unsafe { get_str(s) }.len() as i64
+}
+
+// the char starting at the byte offset `pos`, which is always at a char boundary
+pub extern "C" fn rlan_str_char_at(s: *mut c_void, pos: i64) -> u32 {
+    let s = unsafe { get_str(s) };
+    s[pos as usize..].chars().next().unwrap() as u32
+}
+
+pub extern "C" fn rlan_str_byte_at(s: *mut c_void, pos: i64) -> u8 {
+    unsafe { get_str(s) }.as_bytes()[pos as usize]
+}
+
+// number of bytes the char takes up in UTF-8
+pub extern "C" fn rlan_char_width(c: u32) -> i64 {
+    std::char::from_u32(c).map_or(1, |c| c.len_utf8()) as i64
+}
+
+// `bytes s` is the same str, its type makes loops go over bytes instead of chars
+pub extern "C" fn rlan_str_bytes(s: *mut c_void) -> *mut c_void {
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::rc::Rc;
+
+    #[test]
+    fn heap_is_freed_when_the_script_ends() {
+        let counted = Rc::new(());
+        let s = new_str(String::from("kept"));
+        alloc(Rc::clone(&counted));
+        assert_eq!(unsafe { get_str(s) }, "kept");
+        assert_eq!(Rc::strong_count(&counted), 2);
+        free_heap();
+        assert_eq!(Rc::strong_count(&counted), 1);
+    }
+}
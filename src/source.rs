@@ -0,0 +1,35 @@
+// a script and its name, for turning byte offsets into readable positions
+pub struct Source {
+    pub name: String,
+    pub text: String,
+    line_starts: Vec<usize>,
+}
+
+impl Source {
+    pub fn new(name: String, text: String) -> Self {
+        let mut line_starts = vec![0];
+        for (i, c) in text.char_indices() {
+            if c == '\n' {
+                line_starts.push(i + 1);
+            }
+        }
+        Source {name, text, line_starts}
+    }
+
+    // 1-based line and column
+    pub fn position(&self, offset: usize) -> (usize, usize) {
+        let line = match self.line_starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(next) => next - 1
+        };
+        let start = self.line_starts[line];
+        let col = self.text[start..offset.min(self.text.len())].chars().count() + 1;
+        (line + 1, col)
+    }
+
+    // `file:line:col`
+    pub fn location(&self, offset: usize) -> String {
+        let (line, col) = self.position(offset);
+        format!("{}:{}:{}", self.name, line, col)
+    }
+}
@@ -1,5 +1,346 @@
+use crate::bigint::{BigInt, rlan_bigint_from_int, rlan_bigint_rem};
+use crate::runtime::{self, Handle, rlan_str_bytes, rlan_args};
+use crate::collections;
+use crate::wrapper::{Type, RESULT_OUT_OF_BOUNDS, RESULT_OVERFLOW, RESULT_ARITHMETIC};
+use libc::c_void;
 
+use std::cell::Cell;
+use std::env;
+use std::fs;
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-pub fn stdlib_printint(a1: i64) {
-    println!("{}", a1);
-}
\ No newline at end of file
+// a native function and its rlan signature
+pub struct Native {
+    pub name: &'static str,
+    pub ptr: *mut c_void,
+    pub args: Vec<Type>,
+    pub ret: Type,
+}
+
+// natives of a module are called as `module.name(args)`, the ones of the prelude by their name
+pub struct Module {
+    pub name: &'static str,
+    pub natives: Vec<Native>,
+}
+
+fn native(name: &'static str, ptr: *mut c_void, args: Vec<Type>, ret: Type) -> Native {
+    Native {name, ptr, args, ret}
+}
+
+pub fn prelude() -> Module {
+    Module {name: "", natives: vec![
+        native("printint", stdlib_printint as *mut c_void, vec![Type::int()], Type::void()),
+        native("printstr", stdlib_printstr as *mut c_void, vec![Type::str()], Type::void()),
+        native("print", stdlib_print as *mut c_void, vec![Type::str()], Type::void()),
+        native("printbig", stdlib_printbig as *mut c_void, vec![Type::bigint()], Type::void()),
+        native("bigint", rlan_bigint_from_int as *mut c_void, vec![Type::int()], Type::bigint()),
+        native("bigint_rem", rlan_bigint_rem as *mut c_void, vec![Type::bigint(), Type::bigint()], Type::bigint()),
+        native("printchar", stdlib_printchar as *mut c_void, vec![Type::char()], Type::void()),
+        native("bytes", rlan_str_bytes as *mut c_void, vec![Type::str()], Type::bytes()),
+        native("char_code", stdlib_char_code as *mut c_void, vec![Type::char()], Type::int()),
+        native("from_code", stdlib_from_code as *mut c_void, vec![Type::int()], Type::char()),
+        native("is_alpha", stdlib_is_alpha as *mut c_void, vec![Type::char()], Type::bool()),
+        native("is_digit", stdlib_is_digit as *mut c_void, vec![Type::char()], Type::bool()),
+        native("is_alnum", stdlib_is_alnum as *mut c_void, vec![Type::char()], Type::bool()),
+        native("is_space", stdlib_is_space as *mut c_void, vec![Type::char()], Type::bool()),
+        native("is_upper", stdlib_is_upper as *mut c_void, vec![Type::char()], Type::bool()),
+        native("is_lower", stdlib_is_lower as *mut c_void, vec![Type::char()], Type::bool()),
+        native("is_punct", stdlib_is_punct as *mut c_void, vec![Type::char()], Type::bool()),
+        native("open", file_open as *mut c_void, vec![Type::str(), Type::str()], Type::file()),
+        native("read_line", file_read_line as *mut c_void, vec![Type::file()], Type::str()),
+        native("lines", file_lines as *mut c_void, vec![Type::file()], Type::vec(Type::str())),
+        native("eof", file_eof as *mut c_void, vec![Type::file()], Type::bool()),
+        native("write", file_write as *mut c_void, vec![Type::file(), Type::str()], Type::void()),
+        native("close", file_close as *mut c_void, vec![Type::file()], Type::void()),
+    ]}
+}
+
+// other names of natives declared above, they share the table entry of the native
+pub fn aliases() -> Vec<(&'static str, &'static str)> {
+    vec![
+        ("args", "os.args"),
+        ("exit", "os.exit"),
+        // kept from before files had methods, the same as `file.write(text)`
+        ("io.write", "write"),
+    ]
+}
+
+pub fn modules() -> Vec<Module> {
+    vec![
+        Module {name: "math", natives: vec![
+            native("abs", math_abs as *mut c_void, vec![Type::int()], Type::int()),
+            native("min", math_min as *mut c_void, vec![Type::int(), Type::int()], Type::int()),
+            native("max", math_max as *mut c_void, vec![Type::int(), Type::int()], Type::int()),
+            native("pow", math_pow as *mut c_void, vec![Type::int(), Type::int()], Type::int()),
+            native("sqrt", math_sqrt as *mut c_void, vec![Type::int()], Type::int()),
+            native("gcd", math_gcd as *mut c_void, vec![Type::int(), Type::int()], Type::int()),
+        ]},
+        Module {name: "io", natives: vec![
+            native("read_line", io_read_line as *mut c_void, vec![], Type::str()),
+            native("read_file", io_read_file as *mut c_void, vec![Type::str()], Type::str()),
+            native("write_file", io_write_file as *mut c_void, vec![Type::str(), Type::str()], Type::void()),
+            native("stdin", io_stdin as *mut c_void, vec![], Type::file()),
+            native("stdout", io_stdout as *mut c_void, vec![], Type::file()),
+            native("stderr", io_stderr as *mut c_void, vec![], Type::file()),
+        ]},
+        Module {name: "os", natives: vec![
+            native("args", rlan_args as *mut c_void, vec![], Type::vec(Type::str())),
+            native("env", os_env as *mut c_void, vec![Type::str()], Type::str()),
+            native("exit", os_exit as *mut c_void, vec![Type::int()], Type::void()),
+            native("time", os_time as *mut c_void, vec![], Type::int()),
+        ]},
+        Module {name: "rand", natives: vec![
+            native("seed", rand_seed as *mut c_void, vec![Type::int()], Type::void()),
+            native("next", rand_next as *mut c_void, vec![], Type::int()),
+            native("range", rand_range as *mut c_void, vec![Type::int(), Type::int()], Type::int()),
+        ]},
+    ]
+}
+
+// the print natives write through the stdout handle, like `io.stdout().write(s)`, so an
+// error such as a closed pipe is raised instead of panicking
+fn print_out(text: &str) {
+    if let Err(err) = unsafe { runtime::get_handle(Handle::stdout()) }.write(text) {
+        runtime::raise_io(err);
+    }
+}
+
+pub extern "C" fn stdlib_printint(a1: i64) {
+    print_out(&format!("{}\n", a1));
+}
+
+pub extern "C" fn stdlib_printstr(s: *mut c_void) {
+    print_out(&format!("{}\n", unsafe { runtime::get_str(s) }));
+}
+
+// without the newline
+pub extern "C" fn stdlib_print(s: *mut c_void) {
+    print_out(unsafe { runtime::get_str(s) });
+}
+
+pub extern "C" fn stdlib_printbig(n: *mut c_void) {
+    print_out(&format!("{}\n", unsafe { &*(n as *const BigInt) }));
+}
+
+pub extern "C" fn stdlib_printchar(c: u32) {
+    print_out(&format!("{}\n", to_char(c)));
+}
+
+pub extern "C" fn stdlib_char_code(c: u32) -> i64 {
+    c as i64
+}
+
+pub extern "C" fn stdlib_from_code(n: i64) -> u32 {
+    if n < 0 || n > u32::MAX as i64 || std::char::from_u32(n as u32).is_none() {
+        runtime::raise(RESULT_OUT_OF_BOUNDS as i64, "invalid character code");
+    }
+    n as u32
+}
+
+// chars coming from JIT code are always valid
+pub fn to_char(c: u32) -> char {
+    std::char::from_u32(c).unwrap()
+}
+
+pub extern "C" fn stdlib_is_alpha(c: u32) -> bool {
+    to_char(c).is_alphabetic()
+}
+
+pub extern "C" fn stdlib_is_digit(c: u32) -> bool {
+    to_char(c).is_ascii_digit()
+}
+
+pub extern "C" fn stdlib_is_alnum(c: u32) -> bool {
+    to_char(c).is_alphanumeric()
+}
+
+pub extern "C" fn stdlib_is_space(c: u32) -> bool {
+    to_char(c).is_whitespace()
+}
+
+pub extern "C" fn stdlib_is_upper(c: u32) -> bool {
+    to_char(c).is_uppercase()
+}
+
+pub extern "C" fn stdlib_is_lower(c: u32) -> bool {
+    to_char(c).is_lowercase()
+}
+
+pub extern "C" fn stdlib_is_punct(c: u32) -> bool {
+    to_char(c).is_ascii_punctuation()
+}
+
+pub extern "C" fn math_abs(n: i64) -> i64 {
+    n.checked_abs().unwrap_or_else(|| runtime::raise(RESULT_OVERFLOW as i64, "overflow"))
+}
+
+pub extern "C" fn math_min(a: i64, b: i64) -> i64 {
+    a.min(b)
+}
+
+pub extern "C" fn math_max(a: i64, b: i64) -> i64 {
+    a.max(b)
+}
+
+pub extern "C" fn math_pow(base: i64, exp: i64) -> i64 {
+    if exp < 0 || exp > u32::MAX as i64 {
+        runtime::raise(RESULT_ARITHMETIC as i64, "invalid exponent");
+    }
+    base.checked_pow(exp as u32).unwrap_or_else(|| runtime::raise(RESULT_OVERFLOW as i64, "overflow"))
+}
+
+// the integer square root, rounded down
+pub extern "C" fn math_sqrt(n: i64) -> i64 {
+    if n < 0 {
+        runtime::raise(RESULT_ARITHMETIC as i64, "square root of a negative number");
+    }
+    let mut root = (n as f64).sqrt() as i64;
+    // the float estimate can be off by one for big numbers
+    while root * root > n {
+        root -= 1;
+    }
+    while (root + 1).checked_mul(root + 1).is_some_and(|sq| sq <= n) {
+        root += 1;
+    }
+    root
+}
+
+pub extern "C" fn math_gcd(a: i64, b: i64) -> i64 {
+    let (mut a, mut b) = (a.unsigned_abs(), b.unsigned_abs());
+    while b != 0 {
+        let r = a % b;
+        a = b;
+        b = r;
+    }
+    if a > i64::MAX as u64 {
+        runtime::raise(RESULT_OVERFLOW as i64, "overflow");
+    }
+    a as i64
+}
+
+// a line from stdin without the line break, empty at the end of the input
+pub extern "C" fn io_read_line() -> *mut c_void {
+    file_read_line(Handle::stdin())
+}
+
+pub extern "C" fn io_read_file(path: *mut c_void) -> *mut c_void {
+    match fs::read_to_string(unsafe { runtime::get_str(path) }) {
+        Ok(text) => runtime::new_str(text),
+        Err(err) => runtime::raise_io(err)
+    }
+}
+
+pub extern "C" fn io_write_file(path: *mut c_void, text: *mut c_void) {
+    if let Err(err) = fs::write(unsafe { runtime::get_str(path) }, unsafe { runtime::get_str(text) }) {
+        runtime::raise_io(err);
+    }
+}
+
+pub extern "C" fn io_stdin() -> *mut c_void {
+    Handle::stdin()
+}
+
+pub extern "C" fn io_stdout() -> *mut c_void {
+    Handle::stdout()
+}
+
+pub extern "C" fn io_stderr() -> *mut c_void {
+    Handle::stderr()
+}
+
+pub extern "C" fn file_open(path: *mut c_void, mode: *mut c_void) -> *mut c_void {
+    match Handle::open(unsafe { runtime::get_str(path) }, unsafe { runtime::get_str(mode) }) {
+        Ok(file) => file,
+        Err(err) => runtime::raise_io(err)
+    }
+}
+
+// empty at the end of the file, see `eof`
+pub extern "C" fn file_read_line(file: *mut c_void) -> *mut c_void {
+    match unsafe { runtime::get_handle(file) }.read_line() {
+        Ok(line) => runtime::new_str(line.unwrap_or_default()),
+        Err(err) => runtime::raise_io(err)
+    }
+}
+
+// the remaining lines of the file
+pub extern "C" fn file_lines(file: *mut c_void) -> *mut c_void {
+    let file = unsafe { runtime::get_handle(file) };
+    let mut lines = Vec::new();
+    loop {
+        match file.read_line() {
+            Ok(Some(line)) => lines.push(runtime::new_str(line) as u64),
+            Ok(None) => return collections::new_vec(lines),
+            Err(err) => {
+                drop(lines);
+                runtime::raise_io(err);
+            }
+        }
+    }
+}
+
+pub extern "C" fn file_eof(file: *mut c_void) -> bool {
+    match unsafe { runtime::get_handle(file) }.at_eof() {
+        Ok(eof) => eof,
+        Err(err) => runtime::raise_io(err)
+    }
+}
+
+pub extern "C" fn file_write(file: *mut c_void, text: *mut c_void) {
+    if let Err(err) = unsafe { runtime::get_handle(file) }.write(unsafe { runtime::get_str(text) }) {
+        runtime::raise_io(err);
+    }
+}
+
+pub extern "C" fn file_close(file: *mut c_void) {
+    if let Err(err) = unsafe { runtime::get_handle(file) }.close() {
+        runtime::raise_io(err);
+    }
+}
+
+// the variable's value, empty if it isn't set
+pub extern "C" fn os_env(name: *mut c_void) -> *mut c_void {
+    runtime::new_str(env::var(unsafe { runtime::get_str(name) }).unwrap_or_default())
+}
+
+pub extern "C" fn os_exit(code: i64) {
+    runtime::flush_all();
+    process::exit(code as i32);
+}
+
+// milliseconds since the unix epoch
+pub extern "C" fn os_time() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as i64)
+}
+
+thread_local! {
+    // state of the xorshift64* generator, never zero
+    static RAND_STATE: Cell<u64> = Cell::new(os_time() as u64 | 1);
+}
+
+pub extern "C" fn rand_seed(seed: i64) {
+    // zero would make the generator return only zeros
+    RAND_STATE.with(|s| s.set(if seed == 0 { 0x9E3779B97F4A7C15 } else { seed as u64 }));
+}
+
+// a random non-negative int
+pub extern "C" fn rand_next() -> i64 {
+    RAND_STATE.with(|s| {
+        let mut x = s.get();
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        s.set(x);
+        (x.wrapping_mul(0x2545F4914F6CDD1D) >> 1) as i64
+    })
+}
+
+// a random int in [lo, hi)
+pub extern "C" fn rand_range(lo: i64, hi: i64) -> i64 {
+    if lo >= hi {
+        runtime::raise(RESULT_ARITHMETIC as i64, "empty range");
+    }
+    let width = hi.wrapping_sub(lo) as u64;
+    lo.wrapping_add((rand_next() as u64 % width) as i64)
+}
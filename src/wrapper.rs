@@ -1,8 +1,9 @@
 use crate::bindings::*;
 use std::mem;
 use std::ptr;
-use libc::{c_void};
+use libc::{c_void, c_char};
 use std::convert::TryInto;
+use std::ffi::{CStr, CString};
 
 pub struct Context {
     ptr: *mut _jit_context,
@@ -27,10 +28,81 @@ pub struct Type {
 
 pub type Signature = Type;
 
+// builds (and possibly compiles) the function passed in, returns one of the RESULT_ codes
+pub type OnDemandCompiler = extern "C" fn(*mut c_void) -> i32;
+
+pub const RESULT_OK: i32 = JIT_RESULT_OK as i32;
+pub const RESULT_OVERFLOW: i32 = JIT_RESULT_OVERFLOW;
+pub const RESULT_ARITHMETIC: i32 = JIT_RESULT_ARITHMETIC;
+pub const RESULT_DIVISION_BY_ZERO: i32 = JIT_RESULT_DIVISION_BY_ZERO;
+pub const RESULT_COMPILE_ERROR: i32 = JIT_RESULT_COMPILE_ERROR;
+pub const RESULT_OUT_OF_MEMORY: i32 = JIT_RESULT_OUT_OF_MEMORY;
+pub const RESULT_NULL_REFERENCE: i32 = JIT_RESULT_NULL_REFERENCE;
+pub const RESULT_NULL_FUNCTION: i32 = JIT_RESULT_NULL_FUNCTION;
+pub const RESULT_OUT_OF_BOUNDS: i32 = JIT_RESULT_OUT_OF_BOUNDS;
+
+// turns a builtin exception (one of the RESULT_ codes) into a thrown object
+pub type ExceptionHandler = extern "C" fn(i32) -> *mut c_void;
+
+pub fn exception_set_handler(handler: ExceptionHandler) {
+    unsafe {
+        jit_exception_set_handler(Some(handler));
+    }
+}
+
+// unwinds to the nearest catcher without running destructors of the frames in between
+pub fn exception_throw(object: *mut c_void) -> ! {
+    unsafe {
+        jit_exception_throw(object);
+    }
+    unreachable!()
+}
+
+pub fn exception_take_last() -> *mut c_void {
+    unsafe {
+        jit_exception_get_last_and_clear()
+    }
+}
+
 pub struct Label {
     ptr: *mut jit_label_t,
 }
 
+pub struct StackTrace {
+    ptr: *mut jit_stack_trace,
+}
+
+// function metadata key of the rlan name
+const META_NAME: i32 = 1;
+
+// tags of rlan types which libjit sees as plain pointers
+const TAG_STR: i32 = 1;
+const TAG_BIGINT: i32 = 2;
+// bool is an sbyte, tagged to tell it apart from i8
+const TAG_BOOL: i32 = 3;
+// a unicode scalar value stored in an uint
+const TAG_CHAR: i32 = 4;
+// the bytes of a str, the pointer is the same as the str's
+const TAG_BYTES: i32 = 5;
+// the type of `()` and of statements, unlike void it has a value
+const TAG_UNIT: i32 = 6;
+// an opaque pointer to a runtime file handle
+const TAG_FILE: i32 = 7;
+// a pointer to a runtime vector, the element type is the data of the tag
+const TAG_VEC: i32 = 8;
+// a pointer to a runtime hash map, the data of the tag is a tuple of the key and value types
+const TAG_MAP: i32 = 9;
+
+thread_local! {
+    static STR: Type = Type::tagged(Type::void_ptr(), TAG_STR);
+    static BIGINT: Type = Type::tagged(Type::void_ptr(), TAG_BIGINT);
+    static BOOL: Type = Type::tagged(Type {ptr: unsafe { jit_type_sbyte }}, TAG_BOOL);
+    static CHAR: Type = Type::tagged(Type {ptr: unsafe { jit_type_uint }}, TAG_CHAR);
+    static BYTES: Type = Type::tagged(Type::void_ptr(), TAG_BYTES);
+    static UNIT: Type = Type::tagged(Type {ptr: unsafe { jit_type_ubyte }}, TAG_UNIT);
+    static FILE: Type = Type::tagged(Type::void_ptr(), TAG_FILE);
+}
+
 impl Context {
     pub fn new() -> Self {
         unsafe {
@@ -47,6 +119,13 @@ impl Context {
         }
     }
 
+    // reopen the context for building, e.g. to recompile a function after `finish`
+    pub fn start(&self) {
+        unsafe {
+            jit_context_build_start(self.ptr);
+        }
+    }
+
     pub fn finish(&self) {
         unsafe {
             jit_context_build_end(self.ptr);
@@ -69,26 +148,76 @@ impl Iterator for Context {
         unsafe {
             let nextf = jit_function_next(self.ptr, self.nextfunc);
             self.nextfunc = nextf;
-            if nextf == ptr::null_mut() {
+            if nextf.is_null() {
                 None
             } else {
-                Some(Function {ptr: nextf, argc: jit_type_num_params(jit_function_get_signature(nextf))})
+                Some(Function::from_ptr(nextf))
             }
         }
     }
 }
 
 impl Function {
+    fn from_ptr(ptr: *mut _jit_function) -> Self {
+        unsafe {
+            Function {ptr, argc: jit_type_num_params(jit_function_get_signature(ptr))}
+        }
+    }
+
+    pub fn set_name(&self, name: &str) {
+        unsafe {
+            let name = CString::new(name).unwrap().into_raw();
+            jit_function_set_meta(self.ptr, META_NAME, name as *mut c_void, Some(free_name), 0);
+        }
+    }
+
+    pub fn name(&self) -> Option<String> {
+        unsafe {
+            let name = jit_function_get_meta(self.ptr, META_NAME);
+            if name.is_null() {
+                None
+            } else {
+                Some(CStr::from_ptr(name as *const c_char).to_string_lossy().into_owned())
+            }
+        }
+    }
+
+    pub fn argc(&self) -> usize {
+        self.argc as usize
+    }
+
     pub fn get_params(&self) -> Vec<Value> {
         unsafe {
             let mut params = Vec::new();
             for i in 0..self.argc {
-                params.push(Value::new(jit_value_get_param(self.ptr, i as u32)));
+                params.push(Value::new(jit_value_get_param(self.ptr, i)));
             }
             params
         }
     }
 
+    pub fn return_type(&self) -> Type {
+        unsafe {
+            Type {ptr: jit_type_get_return(jit_function_get_signature(self.ptr))}
+        }
+    }
+
+    // tail calls are only possible between functions with the same signature
+    pub fn same_signature(&self, other: &Function) -> bool {
+        unsafe {
+            let sig = jit_function_get_signature(self.ptr);
+            let other = jit_function_get_signature(other.ptr);
+            let same = |a: jit_type_t, b: jit_type_t| {
+                jit_type_get_kind(a) == jit_type_get_kind(b) && jit_type_get_size(a) == jit_type_get_size(b)
+            };
+            if jit_type_num_params(sig) != jit_type_num_params(other) ||
+                !same(jit_type_get_return(sig), jit_type_get_return(other)) {
+                return false;
+            }
+            (0..jit_type_num_params(sig)).all(|i| same(jit_type_get_param(sig, i), jit_type_get_param(other, i)))
+        }
+    }
+
     pub fn dump(&self) {
         unsafe {
             printfunc(self.ptr);
@@ -103,20 +232,49 @@ impl Function {
     }
 
     pub fn compile(&self) -> i32 {
+        self.compile_at(Function::max_optimization_level())
+    }
+
+    pub fn compile_at(&self, level: u32) -> i32 {
         unsafe {
-            jit_function_set_optimization_level(self.ptr, jit_function_get_max_optimization_level());
+            jit_function_set_optimization_level(self.ptr, level);
             jit_function_compile(self.ptr)
         }
     }
 
-    // execute the function without args and return i32 ('main' signature)
-    pub fn standard_execute(&self) -> i32 {
+    pub fn max_optimization_level() -> u32 {
+        unsafe {
+            jit_function_get_max_optimization_level()
+        }
+    }
+
+    // must be called before the first compilation, otherwise
+    // existing callers keep using the old code
+    pub fn set_recompilable(&self) {
+        unsafe {
+            jit_function_set_recompilable(self.ptr);
+        }
+    }
+
+    // the compiler is invoked by libjit the first time the function is called
+    pub fn set_on_demand_compiler(&self, compiler: OnDemandCompiler) {
+        unsafe {
+            jit_function_set_on_demand_compiler(self.ptr, Some(mem::transmute::<OnDemandCompiler, unsafe extern "C" fn(jit_function_t) -> i32>(compiler)));
+        }
+    }
+
+    // execute the function without args and return its int ('main' signature), which is
+    // a nint, None if an exception wasn't caught
+    pub fn standard_execute(&self) -> Option<i64> {
         unsafe {
             let mut dummy = 0;
-            let mut args : [*mut c_void; 1] = [mem::transmute(&mut dummy)];
-            let mut res : i32 = 0;
-            jit_function_apply(self.ptr, args.as_mut_ptr(), &mut res as *mut i32 as *mut c_void);
-            res
+            let mut args : [*mut c_void; 1] = [&mut dummy as *mut i32 as *mut c_void];
+            let mut res : i64 = 0;
+            if jit_function_apply(self.ptr, args.as_mut_ptr(), &mut res as *mut i64 as *mut c_void) == 0 {
+                None
+            } else {
+                Some(res)
+            }
         }
     }
 
@@ -126,18 +284,44 @@ impl Function {
         }
     }
 
+    // throws an overflow exception instead of wrapping around
+    pub fn i_add_ovf(&self, val1: &Value, val2: &Value) -> Value {
+        unsafe {
+            Value::new(jit_insn_add_ovf(self.ptr, val1.ptr, val2.ptr))
+        }
+    }
+
     pub fn i_sub(&self, val1: &Value, val2: &Value) -> Value {
         unsafe {
             Value::new(jit_insn_sub(self.ptr, val1.ptr, val2.ptr))
         }
     }
 
+    pub fn i_sub_ovf(&self, val1: &Value, val2: &Value) -> Value {
+        unsafe {
+            Value::new(jit_insn_sub_ovf(self.ptr, val1.ptr, val2.ptr))
+        }
+    }
+
     pub fn i_mul(&self, val1: &Value, val2: &Value) -> Value {
         unsafe {
             Value::new(jit_insn_mul(self.ptr, val1.ptr, val2.ptr))
         }
     }
 
+    pub fn i_mul_ovf(&self, val1: &Value, val2: &Value) -> Value {
+        unsafe {
+            Value::new(jit_insn_mul_ovf(self.ptr, val1.ptr, val2.ptr))
+        }
+    }
+
+    // throws on division by zero and when the result overflows
+    pub fn i_div(&self, val1: &Value, val2: &Value) -> Value {
+        unsafe {
+            Value::new(jit_insn_div(self.ptr, val1.ptr, val2.ptr))
+        }
+    }
+
     pub fn i_eq(&self, val1: &Value, val2: &Value) -> Value {
         unsafe {
             Value::new(jit_insn_eq(self.ptr, val1.ptr, val2.ptr))
@@ -192,13 +376,37 @@ impl Function {
         }
     }
 
+    // throws an overflow exception if the value doesn't fit into the type
+    pub fn i_convert_ovf(&self, val: &Value, tp: Type) -> Value {
+        unsafe {
+            Value::new(jit_insn_convert(self.ptr, val.ptr, tp.ptr, 1))
+        }
+    }
+
+    // the result of a conversion has the type without tags,
+    // so the bool is stored into a tagged temporary
+    pub fn i_to_bool(&self, val: &Value) -> Value {
+        let res = Value::create(self, &Type::bool());
+        self.i_store(&self.i_convert(val, Type::bool()), &res);
+        res
+    }
+
     pub fn i_normal_call(&self, f: &Function, args: &[Value]) -> Value {
+        self.call(f, args, 0)
+    }
+
+    // the caller's frame is reused, only valid right before returning the result
+    pub fn i_tail_call(&self, f: &Function, args: &[Value]) -> Value {
+        self.call(f, args, JIT_CALL_TAIL as i32)
+    }
+
+    fn call(&self, f: &Function, args: &[Value], flags: i32) -> Value {
         unsafe {
             let mut argsval : Vec<*mut _jit_value> = Vec::with_capacity(args.len());
             for a in args {
                 argsval.push(a.ptr);
             }
-            Value::new(jit_insn_call(self.ptr, ptr::null(), f.ptr, ptr::null_mut(), argsval.as_mut_ptr(), args.len().try_into().unwrap(), 0))
+            Value::new(jit_insn_call(self.ptr, ptr::null(), f.ptr, ptr::null_mut(), argsval.as_mut_ptr(), args.len().try_into().unwrap(), flags))
         }
     }
 
@@ -217,6 +425,52 @@ impl Function {
         }
     }
 
+    // code emitted afterwards is reported at `offset` in stack traces
+    pub fn i_mark_offset(&self, offset: usize) {
+        unsafe {
+            jit_insn_mark_offset(self.ptr, offset as i32);
+        }
+    }
+
+    pub fn i_throw(&self, val: &Value) {
+        unsafe {
+            jit_insn_throw(self.ptr, val.ptr);
+        }
+    }
+
+    // must be emitted at the start of functions with a catcher
+    pub fn i_uses_catcher(&self) {
+        unsafe {
+            jit_insn_uses_catcher(self.ptr);
+        }
+    }
+
+    // everything emitted afterwards runs when an exception is thrown,
+    // returns the thrown object
+    pub fn i_start_catcher(&self) -> Value {
+        unsafe {
+            Value::new(jit_insn_start_catcher(self.ptr))
+        }
+    }
+
+    pub fn i_rethrow_unhandled(&self) {
+        unsafe {
+            jit_insn_rethrow_unhandled(self.ptr);
+        }
+    }
+
+    pub fn i_default_return(&self) {
+        unsafe {
+            jit_insn_default_return(self.ptr);
+        }
+    }
+
+    pub fn i_branch(&self, brnch: &Label) {
+        unsafe {
+            jit_insn_branch(self.ptr, brnch.ptr);
+        }
+    }
+
     pub fn i_branch_if(&self, val: &Value, brnch: &Label) {
         unsafe {
             jit_insn_branch_if(self.ptr, val.ptr, brnch.ptr);
@@ -237,7 +491,7 @@ impl Function {
 
     pub fn i_alloca(&self, size: i64) -> Value {
         unsafe {
-            Value::new(jit_insn_alloca(self.ptr, Value::constant_long(&self, size).ptr))
+            Value::new(jit_insn_alloca(self.ptr, Value::constant_long(self, size).ptr))
         }
     }
 
@@ -252,6 +506,24 @@ impl Function {
             Value::new(jit_insn_load(self.ptr, dest.ptr))
         }
     }
+
+    pub fn i_address_of(&self, val: &Value) -> Value {
+        unsafe {
+            Value::new(jit_insn_address_of(self.ptr, val.ptr))
+        }
+    }
+
+    pub fn i_load_relative(&self, ptr: &Value, offset: i64, tp: Type) -> Value {
+        unsafe {
+            Value::new(jit_insn_load_relative(self.ptr, ptr.ptr, offset, tp.ptr))
+        }
+    }
+
+    pub fn i_store_relative(&self, ptr: &Value, offset: i64, val: &Value) {
+        unsafe {
+            jit_insn_store_relative(self.ptr, ptr.ptr, offset, val.ptr);
+        }
+    }
 }
 
 impl Value {
@@ -276,16 +548,30 @@ impl Value {
         Value::constant(func, Type::int(), val)
     }
 
+    pub fn constant_ptr(func: &Function, ptr: *mut c_void) -> Self {
+        Value::constant(func, Type::void_ptr(), ptr as i64)
+    }
+
     pub fn constant_void(func: &Function) -> Self {
         Value::constant(func, Type::void(), 0)
     }
 
+    pub fn constant_unit(func: &Function) -> Self {
+        Value::constant(func, Type::unit(), 0)
+    }
+
     pub fn get_type(&self) -> Type {
         unsafe {
             Type {ptr: jit_value_get_type(self.ptr)}
         }
     }
 
+    pub fn is_constant(&self) -> bool {
+        unsafe {
+            jit_value_is_constant(self.ptr) != 0
+        }
+    }
+
     pub fn dump(&self, f: &Function) {
         unsafe {
             printval(f.ptr, self.ptr);
@@ -309,6 +595,40 @@ impl Label {
     }
 }
 
+impl StackTrace {
+    // the calls leading to the current point of execution, innermost first
+    pub fn capture() -> Self {
+        unsafe {
+            StackTrace {ptr: jit_exception_get_stack_trace()}
+        }
+    }
+
+    // the JIT functions on the stack and their offsets marked with `i_mark_offset`
+    pub fn frames(&self, ctx: &Context) -> Vec<(Function, Option<usize>)> {
+        unsafe {
+            let mut frames = Vec::new();
+            for i in 0..jit_stack_trace_get_size(self.ptr) {
+                let func = jit_stack_trace_get_function(ctx.ptr, self.ptr, i);
+                if func.is_null() {
+                    continue; // native code
+                }
+                let offset = jit_stack_trace_get_offset(ctx.ptr, self.ptr, i);
+                let offset = if offset == !0 { None } else { Some(offset as usize) };
+                frames.push((Function::from_ptr(func), offset));
+            }
+            frames
+        }
+    }
+}
+
+impl Drop for StackTrace {
+    fn drop(&mut self) {
+        unsafe {
+            jit_stack_trace_free(self.ptr);
+        }
+    }
+}
+
 impl Type {
 
     pub fn create_signature(params: &[Type], ret: Type) -> Signature {
@@ -340,37 +660,246 @@ impl Type {
     }
 
     pub fn bool() -> Self {
+        BOOL.with(|t| *t)
+    }
+
+    pub fn i8() -> Self {
         unsafe {
             Type {ptr: jit_type_sbyte}
         }
     }
 
+    pub fn u8() -> Self {
+        unsafe {
+            Type {ptr: jit_type_ubyte}
+        }
+    }
+
+    pub fn i16() -> Self {
+        unsafe {
+            Type {ptr: jit_type_short}
+        }
+    }
+
+    pub fn u16() -> Self {
+        unsafe {
+            Type {ptr: jit_type_ushort}
+        }
+    }
+
+    pub fn i32() -> Self {
+        unsafe {
+            Type {ptr: jit_type_int}
+        }
+    }
+
+    pub fn u32() -> Self {
+        unsafe {
+            Type {ptr: jit_type_uint}
+        }
+    }
+
+    pub fn i64() -> Self {
+        unsafe {
+            Type {ptr: jit_type_long}
+        }
+    }
+
+    pub fn u64() -> Self {
+        unsafe {
+            Type {ptr: jit_type_ulong}
+        }
+    }
+
     pub fn void() -> Self {
         unsafe {
             Type {ptr: jit_type_void}
         }
     }
 
+    pub fn void_ptr() -> Self {
+        unsafe {
+            Type {ptr: jit_type_void_ptr}
+        }
+    }
+
+    fn tagged(tp: Type, tag: i32) -> Self {
+        Type::tagged_with(tp, tag, ptr::null_mut())
+    }
+
+    fn tagged_with(tp: Type, tag: i32, data: *mut c_void) -> Self {
+        unsafe {
+            Type {ptr: jit_type_create_tagged(tp.ptr, tag, data, None, 1)}
+        }
+    }
+
+    fn has_tag(&self, tag: i32) -> bool {
+        unsafe {
+            jit_type_get_kind(self.ptr) == JIT_TYPE_FIRST_TAGGED as i32 + tag
+        }
+    }
+
+    pub fn str() -> Self {
+        STR.with(|t| *t)
+    }
+
+    pub fn bigint() -> Self {
+        BIGINT.with(|t| *t)
+    }
+
+    pub fn is_str(&self) -> bool {
+        self.has_tag(TAG_STR)
+    }
+
+    pub fn is_bigint(&self) -> bool {
+        self.has_tag(TAG_BIGINT)
+    }
+
+    pub fn char() -> Self {
+        CHAR.with(|t| *t)
+    }
+
+    pub fn bytes() -> Self {
+        BYTES.with(|t| *t)
+    }
+
+    pub fn is_char(&self) -> bool {
+        self.has_tag(TAG_CHAR)
+    }
+
+    pub fn is_bytes(&self) -> bool {
+        self.has_tag(TAG_BYTES)
+    }
+
+    pub fn unit() -> Self {
+        UNIT.with(|t| *t)
+    }
+
+    pub fn is_unit(&self) -> bool {
+        self.has_tag(TAG_UNIT)
+    }
+
+    pub fn file() -> Self {
+        FILE.with(|t| *t)
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.has_tag(TAG_FILE)
+    }
+
+    pub fn vec(elem: Type) -> Self {
+        Type::tagged_with(Type::void_ptr(), TAG_VEC, elem.ptr as *mut c_void)
+    }
+
+    pub fn is_vec(&self) -> bool {
+        self.has_tag(TAG_VEC)
+    }
+
+    pub fn map(key: Type, value: Type) -> Self {
+        Type::tagged_with(Type::void_ptr(), TAG_MAP, Type::tuple(&[key, value]).ptr as *mut c_void)
+    }
+
+    pub fn is_map(&self) -> bool {
+        self.has_tag(TAG_MAP)
+    }
+
+    fn tag_data(&self) -> Type {
+        unsafe {
+            Type {ptr: jit_type_get_tagged_data(self.ptr) as jit_type_t}
+        }
+    }
+
+    // element type of a vec
+    pub fn elem(&self) -> Type {
+        self.tag_data()
+    }
+
+    pub fn key(&self) -> Type {
+        self.tag_data().field(0)
+    }
+
+    pub fn value(&self) -> Type {
+        self.tag_data().field(1)
+    }
+
+    // whether values of the types are interchangeable
+    pub fn same_as(&self, other: &Type) -> bool {
+        if (self.is_vec() && other.is_vec()) || (self.is_map() && other.is_map()) {
+            return self.tag_data().same_as(&other.tag_data());
+        }
+        if self.is_tuple() && other.is_tuple() {
+            return self.field_count() == other.field_count() &&
+                (0..self.field_count()).all(|i| self.field(i).same_as(&other.field(i)));
+        }
+        unsafe {
+            jit_type_get_kind(self.ptr) == jit_type_get_kind(other.ptr)
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        unsafe {
+            jit_type_get_size(self.ptr) as usize
+        }
+    }
+
+    // tuples are structs with the elements as fields
+    pub fn tuple(elems: &[Type]) -> Self {
+        unsafe {
+            let mut fields: Vec<jit_type_t> = elems.iter().map(|t| t.ptr).collect();
+            Type {ptr: jit_type_create_struct(fields.as_mut_ptr(), fields.len() as u32, 1)}
+        }
+    }
+
+    pub fn is_tuple(&self) -> bool {
+        unsafe {
+            jit_type_get_kind(self.ptr) == (JIT_TYPE_STRUCT as i32)
+        }
+    }
+
+    pub fn field_count(&self) -> usize {
+        unsafe {
+            jit_type_num_fields(self.ptr) as usize
+        }
+    }
+
+    pub fn field(&self, i: usize) -> Type {
+        unsafe {
+            Type {ptr: jit_type_get_field(self.ptr, i as u32)}
+        }
+    }
+
+    pub fn field_offset(&self, i: usize) -> i64 {
+        unsafe {
+            jit_type_get_offset(self.ptr, i as u32) as i64
+        }
+    }
+
     pub fn is_void(&self) -> bool {
         unsafe {
             jit_type_get_kind(self.ptr) == (JIT_TYPE_VOID as i32)
         }
     }
 
+    // any of the integer types, signed or unsigned
     pub fn is_int(&self) -> bool {
         unsafe {
-            jit_type_get_kind(self.ptr) == (JIT_TYPE_NINT as i32) ||
-            jit_type_get_kind(self.ptr) == (JIT_TYPE_LONG as i32) ||
-            jit_type_get_kind(self.ptr) == (JIT_TYPE_INT as i32)
+            let kind = jit_type_get_kind(self.ptr);
+            kind >= (JIT_TYPE_SBYTE as i32) && kind <= (JIT_TYPE_ULONG as i32)
         }
     }
 
-    pub fn is_bool(&self) -> bool {
+    pub fn is_unsigned(&self) -> bool {
         unsafe {
-            jit_type_get_kind(self.ptr) == (JIT_TYPE_SBYTE as i32)
+            let kind = jit_type_get_kind(self.ptr);
+            kind == (JIT_TYPE_UBYTE as i32) || kind == (JIT_TYPE_USHORT as i32) ||
+                kind == (JIT_TYPE_UINT as i32) || kind == (JIT_TYPE_ULONG as i32)
         }
     }
 
+    pub fn is_bool(&self) -> bool {
+        self.has_tag(TAG_BOOL)
+    }
+
     pub fn get_pointed_type(&self) -> Type {
         unsafe {
             Type {ptr: jit_type_get_ref(self.ptr)}
@@ -382,4 +911,7 @@ impl Type {
             printtype(self.ptr)
         }
     }
-}
\ No newline at end of file
+}
+unsafe extern "C" fn free_name(name: *mut c_void) {
+    drop(CString::from_raw(name as *mut c_char));
+}